@@ -0,0 +1,280 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rosc::{encoder, OscMessage, OscPacket, OscType};
+
+use crate::{
+    config::{VORAppIdentifier, VORAppStatus, VORConfig, VRC_LISTENER_NAME},
+    pf::PacketFilter,
+    routing::{route_main, RouteEvent, RouterMsg, RouterOptions},
+};
+
+const BENCH_ADDRESS: &str = "/avatar/parameters/VORBench";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+// Time the router sits idle while its CPU time is measured
+const IDLE_PERIOD: Duration = Duration::from_secs(3);
+const SEND_INTERVAL: Duration = Duration::from_millis(1);
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+const DRAIN_PERIOD: Duration = Duration::from_millis(500);
+const BENCH_QUEUE_SIZE: usize = 4096;
+
+struct BenchResult {
+    mode: &'static str,
+    // Percent of one core used by the whole process while idle
+    idle_cpu: Option<f64>,
+    delivered: usize,
+    expected: usize,
+    latencies: Vec<Duration>,
+}
+
+/*
+    Compare the sync and async routing modes.
+    Runs the router on loopback with local app sockets, measures the process CPU time while no packets flow,
+    then sends sequenced packets and measures the time until each app socket receives them.
+*/
+pub fn bench_main(routes: usize, packets: usize) {
+    println!(
+        "[*] Benchmarking {} route(s), {} packet(s) per mode",
+        routes, packets
+    );
+
+    let mut results = Vec::new();
+    for (mode, async_mode) in [("sync", false), ("async", true)] {
+        match bench_mode(mode, async_mode, routes, packets) {
+            Ok(r) => results.push(r),
+            Err(e) => {
+                println!("[-] {} mode: {}", mode, e);
+                return;
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "{:<6} {:>9} {:>11} {:>10} {:>10} {:>10}",
+        "Mode", "Idle CPU", "Delivered", "p50", "p99", "Max"
+    );
+    for r in results {
+        let idle_cpu = match r.idle_cpu {
+            Some(c) => format!("{:.1}%", c),
+            None => "n/a".to_string(),
+        };
+        println!(
+            "{:<6} {:>9} {:>11} {:>10} {:>10} {:>10}",
+            r.mode,
+            idle_cpu,
+            format!("{}/{}", r.delivered, r.expected),
+            format_latency(percentile(&r.latencies, 0.5)),
+            format_latency(percentile(&r.latencies, 0.99)),
+            format_latency(r.latencies.last().copied()),
+        );
+    }
+    if !cfg!(target_os = "linux") {
+        println!("[*] Idle CPU is only measured on Linux.");
+    }
+}
+
+fn bench_mode(
+    mode: &'static str,
+    async_mode: bool,
+    routes: usize,
+    packets: usize,
+) -> Result<BenchResult, String> {
+    // App sockets the routes send to
+    let mut app_socks = Vec::new();
+    let mut configs = Vec::new();
+    for i in 0..routes {
+        let sock = UdpSocket::bind("127.0.0.1:0").map_err(|e| format!("Failed to bind app socket: {}", e))?;
+        let _ = sock.set_read_timeout(Some(RECV_TIMEOUT));
+        configs.push((
+            VORConfig {
+                app_name: format!("Bench {}", i),
+                app_port: local_port(&sock)?.to_string(),
+                ..Default::default()
+            },
+            i as i64,
+        ));
+        app_socks.push(sock);
+    }
+
+    // Replies are never sent to VRChat so any free port will do
+    let listener_target = format!("127.0.0.1:{}", free_port()?);
+    let vrc_target = format!("127.0.0.1:{}", free_port()?);
+
+    let (router_tx, router_rx): (Sender<RouterMsg>, Receiver<RouterMsg>) = mpsc::channel();
+    let (app_stat_tx, app_stat_rx): (Sender<VORAppIdentifier>, Receiver<VORAppIdentifier>) =
+        mpsc::channel();
+    // Route counters aren't needed here
+    let (route_event_tx, _): (Sender<RouteEvent>, Receiver<RouteEvent>) = mpsc::channel();
+    let opts = RouterOptions {
+        listeners: vec![(VRC_LISTENER_NAME.to_string(), listener_target.clone())],
+        vrc_target,
+        multicast_group: String::new(),
+        pf: PacketFilter::default(),
+        vrc_pf: PacketFilter::default(),
+        queue_size: BENCH_QUEUE_SIZE,
+        async_mode,
+        oscquery: false,
+        metrics_target: None,
+        debug_sender: None,
+        capture_path: None,
+    };
+    let router_thread = thread::spawn(move || route_main(opts, configs, router_rx, app_stat_tx, route_event_tx));
+
+    let result = wait_for_routes(&app_stat_rx, routes).and_then(|_| {
+        println!("[*] {} mode: measuring idle CPU for {:?}", mode, IDLE_PERIOD);
+        let idle_cpu = measure_idle_cpu();
+
+        println!("[*] {} mode: measuring latency", mode);
+        let (delivered, latencies) = measure_latency(&listener_target, app_socks, packets)?;
+        Ok(BenchResult {
+            mode,
+            idle_cpu,
+            delivered,
+            expected: routes * packets,
+            latencies,
+        })
+    });
+
+    let _ = router_tx.send(RouterMsg::ShutdownAll);
+    let _ = router_thread.join();
+    result
+}
+
+// Wait until every route reports Running
+fn wait_for_routes(app_stat_rx: &Receiver<VORAppIdentifier>, routes: usize) -> Result<(), String> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    let mut running = 0;
+    while running < routes {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match app_stat_rx.recv_timeout(timeout) {
+            Ok(s) => match s.status {
+                VORAppStatus::Running => running += 1,
+                VORAppStatus::AppError(e) => return Err(e.msg),
+                _ => {}
+            },
+            Err(_e) => return Err("Routes did not start".to_string()),
+        }
+    }
+    Ok(())
+}
+
+fn measure_idle_cpu() -> Option<f64> {
+    let start_cpu = process_cpu_time();
+    let start = Instant::now();
+    thread::sleep(IDLE_PERIOD);
+    let cpu = process_cpu_time()?.checked_sub(start_cpu?)?;
+    Some(cpu.as_secs_f64() / start.elapsed().as_secs_f64() * 100.)
+}
+
+// User + system CPU time of this process
+#[cfg(target_os = "linux")]
+fn process_cpu_time() -> Option<Duration> {
+    // Clock ticks exposed to userspace (USER_HZ) are 100 per second on Linux
+    const TICKS_PER_SEC: u64 = 100;
+
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // Fields after the parenthesized command name. utime and stime are fields 14 and 15.
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(Duration::from_millis((utime + stime) * 1000 / TICKS_PER_SEC))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_cpu_time() -> Option<Duration> {
+    None
+}
+
+// Send sequenced packets to the listener and collect the latency of every packet an app socket received
+fn measure_latency(
+    listener_target: &str,
+    app_socks: Vec<UdpSocket>,
+    packets: usize,
+) -> Result<(usize, Vec<Duration>), String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let receivers: Vec<thread::JoinHandle<Vec<(usize, Instant)>>> = app_socks
+        .into_iter()
+        .map(|sock| {
+            let stop = stop.clone();
+            thread::spawn(move || receive_sequenced(sock, &stop))
+        })
+        .collect();
+
+    let sock = UdpSocket::bind("127.0.0.1:0").map_err(|e| format!("Failed to bind send socket: {}", e))?;
+    let mut sent_at = Vec::with_capacity(packets);
+    for seq in 0..packets {
+        let buf = encoder::encode(&OscPacket::Message(OscMessage {
+            addr: BENCH_ADDRESS.to_string(),
+            args: vec![OscType::Int(seq as i32)],
+        }))
+        .map_err(|e| format!("Failed to encode packet: {:?}", e))?;
+        sent_at.push(Instant::now());
+        sock.send_to(&buf, listener_target)
+            .map_err(|e| format!("Failed to send packet: {}", e))?;
+        thread::sleep(SEND_INTERVAL);
+    }
+
+    thread::sleep(DRAIN_PERIOD);
+    stop.store(true, Ordering::Relaxed);
+
+    let mut latencies = Vec::new();
+    for r in receivers {
+        for (seq, received) in r.join().unwrap_or_default() {
+            if let Some(sent) = sent_at.get(seq) {
+                latencies.push(received.saturating_duration_since(*sent));
+            }
+        }
+    }
+    latencies.sort();
+    Ok((latencies.len(), latencies))
+}
+
+fn receive_sequenced(sock: UdpSocket, stop: &AtomicBool) -> Vec<(usize, Instant)> {
+    let mut received = Vec::new();
+    let mut buf = [0u8; rosc::decoder::MTU];
+    while !stop.load(Ordering::Relaxed) {
+        let size = match sock.recv(&mut buf) {
+            Ok(s) => s,
+            Err(_e) => continue,
+        };
+        let now = Instant::now();
+        if let Ok((_, OscPacket::Message(msg))) = rosc::decoder::decode_udp(&buf[..size]) {
+            if let Some(OscType::Int(seq)) = msg.args.first() {
+                received.push((*seq as usize, now));
+            }
+        }
+    }
+    received
+}
+
+fn local_port(sock: &UdpSocket) -> Result<u16, String> {
+    sock.local_addr()
+        .map(|a: SocketAddr| a.port())
+        .map_err(|e| e.to_string())
+}
+
+// A loopback UDP port nothing is bound to
+fn free_port() -> Result<u16, String> {
+    let sock = UdpSocket::bind("127.0.0.1:0").map_err(|e| format!("Failed to find a free port: {}", e))?;
+    local_port(&sock)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let i = ((sorted.len() - 1) as f64 * p).round() as usize;
+    Some(sorted[i])
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(l) => format!("{:.0}us", l.as_secs_f64() * 1_000_000.),
+        None => "-".to_string(),
+    }
+}
@@ -0,0 +1,822 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::routing::RouteEvent;
+
+// Native capture file header: magic and format version
+pub const NATIVE_MAGIC: &[u8; 6] = b"VORCAP";
+pub const NATIVE_VERSION: u16 = 1;
+
+// Classic pcap, read only
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAP_SNAPLEN: u32 = 65535;
+
+// pcapng block types, option codes and the byte order magic of the section header
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_EPB: u32 = 6;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_COMMENT: u16 = 1;
+const PCAPNG_IF_NAME: u16 = 2;
+const PCAPNG_IF_TSRESOL: u16 = 9;
+const PCAPNG_EPB_FLAGS: u16 = 2;
+const PCAPNG_INBOUND: u32 = 1;
+// Packet comments holding the packet filter decision
+const PF_ALLOWED_COMMENT: &str = "VOR packet filter: allowed";
+const PF_DROPPED_COMMENT: &str = "VOR packet filter: dropped";
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const IP_TTL: u8 = 64;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    Native,
+    Pcapng,
+}
+
+impl CaptureFormat {
+    // .pcapng and .pcap files are written as pcapng, anything else in the native format
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("pcapng") || ext.eq_ignore_ascii_case("pcap") => {
+                CaptureFormat::Pcapng
+            }
+            _ => CaptureFormat::Native,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureStatus {
+    Recording(String),
+    // Path and packets written
+    Saved(String, u64),
+    Failed(String),
+}
+
+impl fmt::Display for CaptureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureStatus::Recording(path) => write!(f, "Capturing to {}", path),
+            CaptureStatus::Saved(path, packets) => write!(f, "Capture saved to {} ({} packets)", path, packets),
+            CaptureStatus::Failed(e) => write!(f, "Capture failed: {}", e),
+        }
+    }
+}
+
+// A datagram a listener received
+pub struct CaptureRecord {
+    pub received: Instant,
+    pub listener: String,
+    pub from: SocketAddr,
+    // The listener's local address
+    pub to: SocketAddr,
+    // Packet filter decision
+    pub allowed: bool,
+    pub bytes: Vec<u8>,
+}
+
+// Listener side of the recorder. Does nothing while no capture is running.
+#[derive(Clone, Default)]
+pub struct Capture(Arc<RwLock<Option<Sender<CaptureRecord>>>>);
+
+impl Capture {
+    pub fn record(&self, listener: &str, from: SocketAddr, to: SocketAddr, bytes: &[u8], allowed: bool, received: Instant) {
+        if let Some(ref tx) = *self.0.read().unwrap() {
+            let _ = tx.send(CaptureRecord {
+                received,
+                listener: listener.to_string(),
+                from,
+                to,
+                allowed,
+                bytes: bytes.to_vec(),
+            });
+        }
+    }
+}
+
+/*
+    Writes the listeners' datagrams to a capture file on its own thread so listeners never wait on the disk.
+    Statuses are sent as route events.
+*/
+pub struct Recorder {
+    capture: Capture,
+    event_tx: Sender<RouteEvent>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn new(capture: Capture, event_tx: Sender<RouteEvent>) -> Self {
+        Self {
+            capture,
+            event_tx,
+            thread: None,
+        }
+    }
+
+    // Replaces a running capture
+    pub fn start(&mut self, path: &str) {
+        self.stop();
+
+        let format = CaptureFormat::from_path(path);
+        let writer = match File::create(path).and_then(|f| CaptureWriter::new(BufWriter::new(f), format)) {
+            Ok(w) => w,
+            Err(_e) => {
+                send_capture_status(&self.event_tx, CaptureStatus::Failed(format!("{}: {}", path, _e)));
+                return;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        *self.capture.0.write().unwrap() = Some(tx);
+        send_capture_status(&self.event_tx, CaptureStatus::Recording(path.to_string()));
+
+        let event_tx = self.event_tx.clone();
+        let path = path.to_string();
+        self.thread = Some(thread::spawn(move || write_capture(rx, writer, path, event_tx)));
+    }
+
+    // Waits until every received record is written
+    pub fn stop(&mut self) {
+        *self.capture.0.write().unwrap() = None;
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+fn send_capture_status(event_tx: &Sender<RouteEvent>, status: CaptureStatus) {
+    let _ = event_tx.send(RouteEvent::Capture(status));
+}
+
+// Ends when the recorder drops the sender
+fn write_capture<W: Write>(
+    rx: Receiver<CaptureRecord>,
+    mut writer: CaptureWriter<W>,
+    path: String,
+    event_tx: Sender<RouteEvent>,
+) {
+    let mut packets = 0;
+    for record in rx {
+        if let Err(_e) = writer.write(&record) {
+            send_capture_status(&event_tx, CaptureStatus::Failed(format!("{}: {}", path, _e)));
+            return;
+        }
+        packets += 1;
+    }
+    match writer.flush() {
+        Ok(_) => send_capture_status(&event_tx, CaptureStatus::Saved(path, packets)),
+        Err(_e) => send_capture_status(&event_tx, CaptureStatus::Failed(format!("{}: {}", path, _e))),
+    }
+}
+
+pub struct CaptureWriter<W: Write> {
+    out: W,
+    format: CaptureFormat,
+    // Record times are monotonic offsets from the start. pcapng adds them to the wall clock start.
+    start: Instant,
+    start_wall: SystemTime,
+    // pcapng interface of each listener, described before its first packet
+    interfaces: Vec<String>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(mut out: W, format: CaptureFormat) -> io::Result<Self> {
+        match format {
+            CaptureFormat::Native => {
+                out.write_all(NATIVE_MAGIC)?;
+                out.write_all(&NATIVE_VERSION.to_le_bytes())?;
+            }
+            CaptureFormat::Pcapng => {
+                // Version 1.0, section length unknown
+                let mut body = Vec::new();
+                body.extend_from_slice(&PCAPNG_BYTE_ORDER.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&(-1i64).to_le_bytes());
+                out.write_all(&pcapng_block(PCAPNG_SHB, &body))?;
+            }
+        }
+        Ok(Self {
+            out,
+            format,
+            start: Instant::now(),
+            start_wall: SystemTime::now(),
+            interfaces: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, record: &CaptureRecord) -> io::Result<()> {
+        let time = record.received.saturating_duration_since(self.start);
+        let buf = match self.format {
+            CaptureFormat::Native => native_record(time, record),
+            CaptureFormat::Pcapng => {
+                let interface = match self.interfaces.iter().position(|l| *l == record.listener) {
+                    Some(i) => i,
+                    None => {
+                        self.out.write_all(&pcapng_interface(&record.listener))?;
+                        self.interfaces.push(record.listener.clone());
+                        self.interfaces.len() - 1
+                    }
+                };
+                pcapng_packet(interface as u32, self.start_wall + time, record)
+            }
+        };
+        self.out.write_all(&buf)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/*
+    Native record, little endian:
+    u64 nanoseconds since the capture started, u8 packet filter decision (1 allowed, 0 dropped),
+    u8 listener name length + name, u8 source address family (4 or 6) + address + u16 port, u32 datagram length + datagram
+*/
+fn native_record(time: Duration, record: &CaptureRecord) -> Vec<u8> {
+    let listener = &record.listener.as_bytes()[..record.listener.len().min(u8::MAX as usize)];
+    let mut buf = Vec::with_capacity(40 + listener.len() + record.bytes.len());
+    buf.extend_from_slice(&(time.as_nanos() as u64).to_le_bytes());
+    buf.push(record.allowed as u8);
+    buf.push(listener.len() as u8);
+    buf.extend_from_slice(listener);
+    match record.from.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&record.from.port().to_le_bytes());
+    buf.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&record.bytes);
+    buf
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+// pcapng block: type, total length, body padded to 32 bits, total length again
+fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let len = 12 + pad4(body.len());
+    let mut buf = Vec::with_capacity(len);
+    buf.extend_from_slice(&block_type.to_le_bytes());
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    buf.extend_from_slice(body);
+    buf.resize(len - 4, 0);
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    buf
+}
+
+// Option code, length and value padded to 32 bits
+fn pcapng_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(pad4(buf.len()), 0);
+}
+
+// pcapng interface named after the listener: raw IPv4/IPv6 packets with nanosecond timestamps
+fn pcapng_interface(listener: &str) -> Vec<u8> {
+    let name = &listener.as_bytes()[..listener.len().min(u8::MAX as usize)];
+    let mut body = Vec::new();
+    body.extend_from_slice(&(LINKTYPE_RAW as u16).to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+    pcapng_option(&mut body, PCAPNG_IF_NAME, name);
+    pcapng_option(&mut body, PCAPNG_IF_TSRESOL, &[9]);
+    pcapng_option(&mut body, PCAPNG_OPT_END, &[]);
+    pcapng_block(PCAPNG_IDB, &body)
+}
+
+/*
+    pcapng enhanced packet of the datagram in an IP/UDP packet from the source to the listener.
+    The packet filter decision is the packet's comment (frame.comment in Wireshark).
+*/
+fn pcapng_packet(interface: u32, time: SystemTime, record: &CaptureRecord) -> Vec<u8> {
+    let packet = ip_udp_packet(record.from, record.to, &record.bytes);
+    let nanos = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    let comment = if record.allowed { PF_ALLOWED_COMMENT } else { PF_DROPPED_COMMENT };
+
+    let mut body = Vec::with_capacity(64 + packet.len());
+    body.extend_from_slice(&interface.to_le_bytes());
+    body.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(nanos as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    body.extend_from_slice(&packet);
+    body.resize(pad4(body.len()), 0);
+    pcapng_option(&mut body, PCAPNG_OPT_COMMENT, comment.as_bytes());
+    pcapng_option(&mut body, PCAPNG_EPB_FLAGS, &PCAPNG_INBOUND.to_le_bytes());
+    pcapng_option(&mut body, PCAPNG_OPT_END, &[]);
+    pcapng_block(PCAPNG_EPB, &body)
+}
+
+fn ip_udp_packet(from: SocketAddr, to: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_len = (8 + payload.len()) as u16;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend_from_slice(&from.port().to_be_bytes());
+    udp.extend_from_slice(&to.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+
+    // Dual stack listeners can see IPv4 sources, use IPv6 for both if either is IPv6
+    let (src, dst) = match (from.ip(), to.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let pseudo = [&src.octets()[..], &dst.octets(), &[0, IPPROTO_UDP], &udp_len.to_be_bytes()].concat();
+            set_udp_checksum(&mut udp, &pseudo);
+
+            let mut ip = Vec::with_capacity(20 + udp.len());
+            ip.extend_from_slice(&[0x45, 0]);
+            ip.extend_from_slice(&(20 + udp_len).to_be_bytes());
+            // Identification, flags and fragment offset
+            ip.extend_from_slice(&[0, 0, 0, 0]);
+            ip.extend_from_slice(&[IP_TTL, IPPROTO_UDP, 0, 0]);
+            ip.extend_from_slice(&src.octets());
+            ip.extend_from_slice(&dst.octets());
+            let sum = internet_checksum(&ip);
+            ip[10..12].copy_from_slice(&sum.to_be_bytes());
+            ip.extend_from_slice(&udp);
+            return ip;
+        }
+        (src, dst) => (ipv6(src), ipv6(dst)),
+    };
+
+    let pseudo = [&src[..], &dst, &(udp_len as u32).to_be_bytes(), &[0, 0, 0, IPPROTO_UDP]].concat();
+    set_udp_checksum(&mut udp, &pseudo);
+
+    let mut ip = Vec::with_capacity(40 + udp.len());
+    ip.extend_from_slice(&[0x60, 0, 0, 0]);
+    ip.extend_from_slice(&udp_len.to_be_bytes());
+    ip.extend_from_slice(&[IPPROTO_UDP, IP_TTL]);
+    ip.extend_from_slice(&src);
+    ip.extend_from_slice(&dst);
+    ip.extend_from_slice(&udp);
+    ip
+}
+
+fn ipv6(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+        IpAddr::V6(v6) => v6.octets(),
+    }
+}
+
+fn set_udp_checksum(udp: &mut [u8], pseudo_header: &[u8]) {
+    let sum = internet_checksum(&[pseudo_header, udp].concat());
+    // 0 means no checksum, a computed 0 is sent as all ones
+    let sum = if sum == 0 { 0xffff } else { sum };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+}
+
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|w| u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+// A datagram read back from a capture file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    // Time since the first packet
+    pub time: Duration,
+    // Not in classic pcap files
+    pub listener: Option<String>,
+    pub from: SocketAddr,
+    // Packet filter decision, not in classic pcap files
+    pub allowed: Option<bool>,
+    pub bytes: Vec<u8>,
+}
+
+// Read a native, pcapng or pcap capture file (Detected from its header)
+pub fn read_capture(path: &str) -> Result<Vec<CapturedPacket>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let packets = if data.starts_with(NATIVE_MAGIC) {
+        parse_native(&data)
+    } else if data.starts_with(&PCAPNG_SHB.to_le_bytes()) {
+        parse_pcapng(&data)
+    } else {
+        parse_pcap(&data)
+    };
+    packets.ok_or_else(|| format!("{}: Not a valid VOR or pcap capture file", path))
+}
+
+// Reads the fields of a capture file in its byte order
+struct Fields<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (field, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(field)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.take(2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b = self.take(4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let b = self.take(8)?.try_into().ok()?;
+        Some(if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) })
+    }
+}
+
+fn parse_native(data: &[u8]) -> Option<Vec<CapturedPacket>> {
+    let mut f = Fields { data, big_endian: false };
+    f.take(NATIVE_MAGIC.len())?;
+    if f.u16()? != NATIVE_VERSION {
+        return None;
+    }
+
+    let mut packets: Vec<CapturedPacket> = Vec::new();
+    while !f.data.is_empty() {
+        let time = Duration::from_nanos(f.u64()?);
+        let allowed = f.u8()? != 0;
+        let listener_len = f.u8()? as usize;
+        let listener = String::from_utf8_lossy(f.take(listener_len)?).to_string();
+        let ip = match f.u8()? {
+            4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(f.take(4)?).ok()?)),
+            6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(f.take(16)?).ok()?)),
+            _ => return None,
+        };
+        let port = f.u16()?;
+        let len = f.u32()? as usize;
+        packets.push(CapturedPacket {
+            time,
+            listener: Some(listener),
+            from: SocketAddr::new(ip, port),
+            allowed: Some(allowed),
+            bytes: f.take(len)?.to_vec(),
+        });
+    }
+
+    // Times count from the capture start, make them count from the first packet
+    let first = packets.first().map(|p| p.time).unwrap_or_default();
+    for p in packets.iter_mut() {
+        p.time = p.time.saturating_sub(first);
+    }
+    Some(packets)
+}
+
+// UDP datagrams in a pcap file. Other packets are skipped.
+fn parse_pcap(data: &[u8]) -> Option<Vec<CapturedPacket>> {
+    let (big_endian, nanos) = match data.get(..4)?.try_into().ok().map(u32::from_le_bytes)? {
+        PCAP_MAGIC => (false, false),
+        PCAP_MAGIC_NANOS => (false, true),
+        m if m.swap_bytes() == PCAP_MAGIC => (true, false),
+        m if m.swap_bytes() == PCAP_MAGIC_NANOS => (true, true),
+        _ => return None,
+    };
+    let mut f = Fields { data, big_endian };
+    f.take(20)?;
+    let linktype = f.u32()?;
+    if !matches!(linktype, LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 | LINKTYPE_ETHERNET) {
+        return None;
+    }
+
+    let mut packets = Vec::new();
+    let mut first = None;
+    while !f.data.is_empty() {
+        let secs = f.u32()? as u64;
+        let frac = f.u32()? as u64;
+        let incl_len = f.u32()? as usize;
+        f.u32()?;
+        let frame = f.take(incl_len)?;
+
+        let time = Duration::from_secs(secs) + if nanos { Duration::from_nanos(frac) } else { Duration::from_micros(frac) };
+        if let Some((from, payload)) = ip_packet(linktype, frame).and_then(udp_datagram) {
+            let start = *first.get_or_insert(time);
+            packets.push(CapturedPacket {
+                time: time.saturating_sub(start),
+                listener: None,
+                from,
+                allowed: None,
+                bytes: payload.to_vec(),
+            });
+        }
+    }
+    Some(packets)
+}
+
+struct PcapngInterface {
+    linktype: u32,
+    name: Option<String>,
+    tsresol: u8,
+}
+
+/*
+    UDP datagrams in a pcapng file. Other packets and blocks are skipped.
+    The listener is the interface name and the packet filter decision comes from VOR's packet comments.
+*/
+fn parse_pcapng(data: &[u8]) -> Option<Vec<CapturedPacket>> {
+    let mut f = Fields { data, big_endian: false };
+    let mut interfaces: Vec<PcapngInterface> = Vec::new();
+    let mut packets = Vec::new();
+    let mut first = None;
+    while !f.data.is_empty() {
+        // The section header type reads the same in both byte orders, its byte order magic follows the length
+        let block_type = f.u32()?;
+        if block_type == PCAPNG_SHB {
+            f.big_endian = match f.data.get(4..8)?.try_into().ok().map(u32::from_le_bytes)? {
+                PCAPNG_BYTE_ORDER => false,
+                m if m.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+                _ => return None,
+            };
+            interfaces.clear();
+        }
+        let len = f.u32()? as usize;
+        let mut body = Fields {
+            data: f.take(len.checked_sub(12)?)?,
+            big_endian: f.big_endian,
+        };
+        f.u32()?;
+
+        match block_type {
+            PCAPNG_IDB => {
+                let mut interface = PcapngInterface {
+                    linktype: body.u16()? as u32,
+                    name: None,
+                    tsresol: 6,
+                };
+                body.take(6)?;
+                for (code, value) in pcapng_options(&mut body) {
+                    match code {
+                        PCAPNG_IF_NAME => interface.name = Some(String::from_utf8_lossy(value).to_string()),
+                        PCAPNG_IF_TSRESOL => interface.tsresol = *value.first()?,
+                        _ => {}
+                    }
+                }
+                interfaces.push(interface);
+            }
+            PCAPNG_EPB => {
+                let interface = interfaces.get(body.u32()? as usize)?;
+                let ticks = (body.u32()? as u64) << 32 | body.u32()? as u64;
+                let incl_len = body.u32()? as usize;
+                body.u32()?;
+                let frame = body.take(incl_len)?;
+                body.take(pad4(incl_len) - incl_len)?;
+                let allowed = pcapng_options(&mut body)
+                    .into_iter()
+                    .filter(|(code, _)| *code == PCAPNG_OPT_COMMENT)
+                    .find_map(|(_, value)| match value {
+                        v if v == PF_ALLOWED_COMMENT.as_bytes() => Some(true),
+                        v if v == PF_DROPPED_COMMENT.as_bytes() => Some(false),
+                        _ => None,
+                    });
+
+                let time = pcapng_time(ticks, interface.tsresol);
+                if let Some((from, payload)) = ip_packet(interface.linktype, frame).and_then(udp_datagram) {
+                    let start = *first.get_or_insert(time);
+                    packets.push(CapturedPacket {
+                        time: time.saturating_sub(start),
+                        listener: interface.name.clone(),
+                        from,
+                        allowed,
+                        bytes: payload.to_vec(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(packets)
+}
+
+// Option codes and values up to the end of the options
+fn pcapng_options<'a>(f: &mut Fields<'a>) -> Vec<(u16, &'a [u8])> {
+    let mut options = Vec::new();
+    loop {
+        let (code, len) = match (f.u16(), f.u16()) {
+            (Some(code), Some(len)) if code != PCAPNG_OPT_END => (code, len as usize),
+            _ => break,
+        };
+        match f.take(pad4(len)) {
+            Some(value) => options.push((code, &value[..len])),
+            None => break,
+        }
+    }
+    options
+}
+
+// Timestamp units are 10^-n seconds, or 2^-n seconds with the high bit set
+fn pcapng_time(ticks: u64, tsresol: u8) -> Duration {
+    let n = (tsresol & 0x7f) as u32;
+    let nanos = if tsresol & 0x80 == 0 {
+        ticks as u128 * 1_000_000_000 / 10u128.checked_pow(n).unwrap_or(u128::MAX)
+    } else {
+        (ticks as u128 * 1_000_000_000) >> n
+    };
+    Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+}
+
+// The IP packet in a frame, None for other frames
+fn ip_packet(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(frame),
+        // Only untagged IPv4/IPv6 frames
+        LINKTYPE_ETHERNET => match frame.get(12..14) {
+            Some([0x08, 0x00]) | Some([0x86, 0xdd]) => frame.get(14..),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Source and payload of an unfragmented UDP packet
+fn udp_datagram(ip: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    let (src, udp) = match ip.first()? >> 4 {
+        4 => {
+            let ihl = (ip[0] & 0x0f) as usize * 4;
+            if *ip.get(9)? != IPPROTO_UDP {
+                return None;
+            }
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(ip.get(12..16)?).ok()?);
+            (IpAddr::V4(src), ip.get(ihl..)?)
+        }
+        6 => {
+            if *ip.get(6)? != IPPROTO_UDP {
+                return None;
+            }
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(ip.get(8..24)?).ok()?);
+            (IpAddr::V6(src), ip.get(40..)?)
+        }
+        _ => return None,
+    };
+    let port = u16::from_be_bytes(udp.get(0..2)?.try_into().ok()?);
+    let len = u16::from_be_bytes(udp.get(4..6)?.try_into().ok()?) as usize;
+    Some((SocketAddr::new(src, port), udp.get(8..len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(from: &str, to: &str, bytes: &[u8]) -> CaptureRecord {
+        CaptureRecord {
+            received: Instant::now(),
+            listener: "VRChat".to_string(),
+            from: from.parse().unwrap(),
+            to: to.parse().unwrap(),
+            allowed: true,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn native_record_layout() {
+        let r = record("192.168.1.2:9001", "127.0.0.1:9001", b"/foo\0\0\0\0");
+        let buf = native_record(Duration::from_nanos(1500), &r);
+
+        assert_eq!(buf[..8], 1500u64.to_le_bytes());
+        assert_eq!(buf[8], 1);
+        assert_eq!(&buf[9..16], b"\x06VRChat");
+        assert_eq!(buf[16..21], [4, 192, 168, 1, 2]);
+        assert_eq!(buf[21..23], 9001u16.to_le_bytes());
+        assert_eq!(buf[23..27], 8u32.to_le_bytes());
+        assert_eq!(&buf[27..], b"/foo\0\0\0\0");
+    }
+
+    #[test]
+    fn ip_udp_checksums_verify() {
+        // A correct checksum sums to zero when included
+        let v4 = ip_udp_packet("192.168.1.2:9001".parse().unwrap(), "127.0.0.1:9000".parse().unwrap(), b"/foo\0\0\0\0,\0\0\0");
+        assert_eq!(v4.len(), 20 + 8 + 12);
+        assert_eq!(internet_checksum(&v4[..20]), 0);
+        let pseudo = [&v4[12..20], &[0, IPPROTO_UDP], &v4[24..26]].concat();
+        assert_eq!(internet_checksum(&[&pseudo[..], &v4[20..]].concat()), 0);
+
+        // IPv4 sources on an IPv6 listener are written as mapped addresses
+        let v6 = ip_udp_packet("192.168.1.2:9001".parse().unwrap(), "[::]:9000".parse().unwrap(), b"/foo\0\0\0\0,\0\0\0");
+        assert_eq!(v6[0] >> 4, 6);
+        assert_eq!(v6[8..24], "::ffff:192.168.1.2".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        let pseudo = [&v6[8..40], &20u32.to_be_bytes(), &[0, 0, 0, IPPROTO_UDP]].concat();
+        assert_eq!(internet_checksum(&[&pseudo[..], &v6[40..]].concat()), 0);
+    }
+
+    #[test]
+    fn pcapng_file_layout() {
+        let mut writer = CaptureWriter::new(Vec::new(), CaptureFormat::Pcapng).unwrap();
+        writer.write(&record("127.0.0.1:50000", "127.0.0.1:9001", b"/foo\0\0\0\0")).unwrap();
+        writer.write(&record("127.0.0.1:50000", "127.0.0.1:9001", b"/foo\0\0\0\0")).unwrap();
+        let buf = writer.out;
+
+        // Section header
+        assert_eq!(buf[..4], PCAPNG_SHB.to_le_bytes());
+        assert_eq!(buf[4..8], 28u32.to_le_bytes());
+        assert_eq!(buf[8..12], PCAPNG_BYTE_ORDER.to_le_bytes());
+        // One interface for the listener, named after it
+        let idb = &buf[28..];
+        assert_eq!(idb[..4], PCAPNG_IDB.to_le_bytes());
+        assert_eq!(idb[8..10], (LINKTYPE_RAW as u16).to_le_bytes());
+        assert_eq!(&idb[20..26], b"VRChat");
+        let idb_len = u32::from_le_bytes(idb[4..8].try_into().unwrap()) as usize;
+        // Packets with the decision as their comment
+        let epb = &idb[idb_len..];
+        assert_eq!(epb[..4], PCAPNG_EPB.to_le_bytes());
+        assert_eq!(epb[8..12], 0u32.to_le_bytes());
+        assert_eq!(epb[20..24], 36u32.to_le_bytes());
+        assert_eq!(epb[64..66], PCAPNG_OPT_COMMENT.to_le_bytes());
+        assert_eq!(&epb[68..68 + PF_ALLOWED_COMMENT.len()], PF_ALLOWED_COMMENT.as_bytes());
+        let epb_len = u32::from_le_bytes(epb[4..8].try_into().unwrap()) as usize;
+        assert_eq!(epb[epb_len..epb_len + 4], PCAPNG_EPB.to_le_bytes());
+        assert_eq!(buf.len(), 28 + idb_len + 2 * epb_len);
+
+        assert_eq!(CaptureFormat::from_path("session.pcapng"), CaptureFormat::Pcapng);
+        assert_eq!(CaptureFormat::from_path("session.PCAP"), CaptureFormat::Pcapng);
+        assert_eq!(CaptureFormat::from_path("session.vorcap"), CaptureFormat::Native);
+    }
+
+    #[test]
+    fn reads_classic_pcap() {
+        let packet = ip_udp_packet("192.168.1.2:9001".parse().unwrap(), "127.0.0.1:9000".parse().unwrap(), b"/foo\0\0\0\0");
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&PCAP_MAGIC.to_be_bytes());
+        buf.extend_from_slice(&[0, 2, 0, 4]);
+        buf.extend_from_slice(&[0; 8]);
+        buf.extend_from_slice(&PCAP_SNAPLEN.to_be_bytes());
+        buf.extend_from_slice(&LINKTYPE_RAW.to_be_bytes());
+        for usecs in [0u32, 1500] {
+            buf.extend_from_slice(&1u32.to_be_bytes());
+            buf.extend_from_slice(&usecs.to_be_bytes());
+            buf.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&packet);
+        }
+
+        let packets = parse_pcap(&buf).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].time, Duration::from_micros(1500));
+        assert_eq!(packets[1].from, "192.168.1.2:9001".parse().unwrap());
+        assert_eq!(packets[1].listener, None);
+        assert_eq!(packets[1].allowed, None);
+        assert_eq!(packets[1].bytes, b"/foo\0\0\0\0");
+    }
+
+    #[test]
+    fn captures_read_back() {
+        for format in [CaptureFormat::Native, CaptureFormat::Pcapng] {
+            let mut writer = CaptureWriter::new(Vec::new(), format).unwrap();
+            let mut first = record("192.168.1.2:9001", "127.0.0.1:9001", b"/foo\0\0\0\0");
+            first.received = writer.start + Duration::from_millis(5);
+            let mut second = record("[fe80::1]:50000", "[::]:9001", b"/bar\0\0\0\0");
+            second.received = writer.start + Duration::from_millis(25);
+            second.allowed = false;
+            writer.write(&first).unwrap();
+            writer.write(&second).unwrap();
+
+            let packets = match format {
+                CaptureFormat::Native => parse_native(&writer.out),
+                CaptureFormat::Pcapng => parse_pcapng(&writer.out),
+            }
+            .unwrap();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].time, Duration::ZERO);
+            assert_eq!(packets[1].time, Duration::from_millis(20));
+            assert_eq!(packets[1].from, second.from);
+            assert_eq!(packets[1].bytes, second.bytes);
+            assert_eq!(packets[0].listener.as_deref(), Some("VRChat"));
+            assert_eq!(packets[0].allowed, Some(true));
+            assert_eq!(packets[1].allowed, Some(false));
+        }
+    }
+}
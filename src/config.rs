@@ -2,7 +2,10 @@
 use crate::vorutils::get_user_home_dir;
 use crate::{
     vorerr::VORAppError,
-    vorutils::{file_exists, path_exists}, pf::PacketFilter,
+    vorutils::{file_exists, host_port, path_exists}, pf::PacketFilter, rewrite::RewriteRules,
+    ratelimit::RateLimit, dedup::Dedup, transport::Transport,
+    websocket::WsFormat, stats::{ListenerStats, RouteStats}, queue::QueuePolicy,
+    supervisor::RestartStats,
 };
 use core::fmt;
 use directories::BaseDirs;
@@ -13,6 +16,8 @@ use std::fs;
 pub struct VORConfigWrapper {
     pub config_data: VORConfig,
     pub config_path: String,
+    // Stable id used to address the app's route while the router is running
+    pub route_id: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,16 +27,131 @@ pub struct VORConfig {
     //pub bind_port: String,
     //pub bind_host: String,
     pub app_name: String,
+    // Forward replies received from the app back to VRChat
+    #[serde(default)]
+    pub vrc_return: bool,
+    // Route packet filter applied after the global packet filter
+    #[serde(default)]
+    pub pf: Option<PacketFilter>,
+    // OSC address rewrite rules applied before sending to the app
+    #[serde(default)]
+    pub rewrite: Option<RewriteRules>,
+    // Max messages per second and per-address coalescing
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    // Skip messages whose arguments didn't change
+    #[serde(default)]
+    pub dedup: Option<Dedup>,
+    // L4 protocol used to send to the app
+    #[serde(default)]
+    pub transport: Transport,
+    // Message format for WebSocket routes
+    #[serde(default)]
+    pub ws_format: WsFormat,
+    // Socket file for Unix datagram routes
+    #[serde(default)]
+    pub unix_path: String,
+    // Multicast app hosts: TTL (empty for OS default) and whether this machine receives its own packets
+    #[serde(default)]
+    pub multicast_ttl: String,
+    #[serde(default = "default_multicast_loop")]
+    pub multicast_loop: bool,
+    // The app host is a subnet broadcast address (192.168.1.255). 255.255.255.255 is always sent as broadcast.
+    #[serde(default)]
+    pub broadcast: bool,
+    // What the route's queue drops when the app can't keep up
+    #[serde(default)]
+    pub queue_policy: QueuePolicy,
+    // UDP only: connect the route's socket and report when the app is not listening
+    #[serde(default)]
+    pub health_check: bool,
+}
+
+fn default_multicast_loop() -> bool {
+    true
+}
+
+// New app defaults
+impl Default for VORConfig {
+    fn default() -> Self {
+        VORConfig {
+            app_port: "9100".to_string(),
+            app_host: "127.0.0.1".to_string(),
+            app_name: "New App".to_string(),
+            vrc_return: false,
+            pf: None,
+            rewrite: None,
+            rate_limit: None,
+            dedup: None,
+            transport: Transport::Udp,
+            ws_format: WsFormat::Binary,
+            unix_path: String::new(),
+            multicast_ttl: String::new(),
+            multicast_loop: true,
+            broadcast: false,
+            queue_policy: QueuePolicy::DropOldest,
+            health_check: false,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RouterConfig {
     pub bind_host: String,
     pub bind_port: String,
-    //pub vrc_host: String,
-    //pub vrc_port: String,
+    #[serde(default = "default_vrc_host")]
+    pub vrc_host: String,
+    #[serde(default = "default_vrc_port")]
+    pub vrc_port: String,
+    // Multicast group the listener joins (empty for none)
+    #[serde(default)]
+    pub multicast_group: String,
     pub vor_buffer_size: String,
     pub async_mode: bool,
+    // Extra listeners merged into the router with the VRChat listener (bind_host:bind_port)
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    // Advertise the VRChat listener over OSCQuery/mDNS
+    #[serde(default)]
+    pub oscquery: bool,
+    // Prometheus/OpenMetrics endpoint (empty port disables it)
+    #[serde(default = "default_metrics_host")]
+    pub metrics_host: String,
+    #[serde(default)]
+    pub metrics_port: String,
+}
+
+// Name shown for packets received on bind_host:bind_port
+pub const VRC_LISTENER_NAME: &str = "VRChat";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListenerConfig {
+    pub name: String,
+    pub bind_host: String,
+    pub bind_port: String,
+}
+
+impl RouterConfig {
+    // (name, host:port) of every listener. The VRChat listener is first.
+    pub fn listener_targets(&self) -> Vec<(String, String)> {
+        let mut targets = vec![(
+            VRC_LISTENER_NAME.to_string(),
+            host_port(&self.bind_host, &self.bind_port),
+        )];
+        for l in &self.listeners {
+            targets.push((l.name.clone(), host_port(&l.bind_host, &l.bind_port)));
+        }
+        targets
+    }
+
+    // host:port of the metrics endpoint if it's enabled
+    pub fn metrics_target(&self) -> Option<String> {
+        if self.metrics_port.is_empty() {
+            None
+        } else {
+            Some(host_port(&self.metrics_host, &self.metrics_port))
+        }
+    }
 }
 
 impl Default for RouterConfig {
@@ -39,14 +159,31 @@ impl Default for RouterConfig {
         RouterConfig {
             bind_host: "127.0.0.1".to_string(),
             bind_port: "9001".to_string(),
-            //vrc_host: "127.0.0.1".to_string(),
-            //vrc_port: "9000".to_string(),
+            vrc_host: default_vrc_host(),
+            vrc_port: default_vrc_port(),
+            multicast_group: String::new(),
             vor_buffer_size: "4096".to_string(),
             async_mode: true,
+            listeners: Vec::new(),
+            oscquery: false,
+            metrics_host: default_metrics_host(),
+            metrics_port: String::new(),
         }
     }
 }
 
+fn default_metrics_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_vrc_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_vrc_port() -> String {
+    "9000".to_string()
+}
+
 pub struct VORAppIdentifier {
     pub index: i64,
     pub status: VORAppStatus,
@@ -62,7 +199,24 @@ pub enum VORAppStatus {
     Disabled,
     Stopped,
     Running,
+    Paused,
+    // TCP route waiting for the app to accept the connection
+    Connecting,
+    // TCP route lost its connection and is reconnecting
+    Disconnected(String),
+    // WebSocket route serving this many clients
+    Clients(usize),
+    // UDP route in health mode whose app refuses packets or has no socket bound
+    NotListening,
     AppError(VORAppError),
+    // Route failed and is restarted after the backoff
+    Restarting(RestartStats),
+    // Traffic counters update (does not change the app's status)
+    Stats(RouteStats),
+    // Listener totals update, sent with index -1
+    Listener(String, ListenerStats),
+    // Warning shown next to the app's status, None clears it (does not change the app's status)
+    Warning(Option<String>),
 }
 
 impl fmt::Display for VORAppStatus {
@@ -71,7 +225,17 @@ impl fmt::Display for VORAppStatus {
             VORAppStatus::Disabled => write!(f, "Disabled"),
             VORAppStatus::Stopped => write!(f, "Stopped"),
             VORAppStatus::Running => write!(f, "Running"),
+            VORAppStatus::Paused => write!(f, "Paused"),
+            VORAppStatus::Connecting => write!(f, "Connecting"),
+            VORAppStatus::Disconnected(e) => write!(f, "Disconnected: {}", e),
+            VORAppStatus::Clients(n) => write!(f, "Running ({} clients)", n),
+            VORAppStatus::NotListening => write!(f, "App not listening"),
             VORAppStatus::AppError(e) => write!(f, "{}: {}", e.msg, e.id),
+            VORAppStatus::Restarting(s) => write!(f, "Restarting in {}s ({})", s.backoff.as_secs(), s),
+            VORAppStatus::Stats(s) => write!(f, "{}", s),
+            VORAppStatus::Listener(name, s) => write!(f, "{}: {}", name, s),
+            VORAppStatus::Warning(Some(w)) => write!(f, "Warning: {}", w),
+            VORAppStatus::Warning(None) => write!(f, "Warning cleared"),
         }
     }
 }
@@ -96,6 +260,11 @@ pub enum InputValidation {
     AH(bool),
     BP(bool),
     BH(bool),
+    RW(String),
+    RL(bool),
+    DD(bool),
+    UP(bool),
+    MT(bool),
     CLEAN,
 }
 
@@ -115,6 +284,11 @@ impl fmt::Display for InputValidation {
             InputValidation::AP(_b) => write!(f, "App port: Invalid input."),
             InputValidation::BH(_b) => write!(f, "Bind Host: Invalid input."),
             InputValidation::BP(_b) => write!(f, "Bind Port: Invalid input."),
+            InputValidation::RW(e) => write!(f, "Address rewriting: {}", e),
+            InputValidation::RL(_b) => write!(f, "Rate limit: Invalid input."),
+            InputValidation::DD(_b) => write!(f, "Deduplication keepalive: Invalid input."),
+            InputValidation::UP(_b) => write!(f, "Unix socket path: Invalid input."),
+            InputValidation::MT(_b) => write!(f, "Multicast TTL: Invalid input."),
             InputValidation::CLEAN => write!(f, "CLEAN"),
         }
     }
@@ -136,7 +310,37 @@ pub fn vor_root() -> Option<String> {
     Some(path_str)
 }
 
-fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
+fn read_pf_config(pf_config_file: String) -> PacketFilter {
+    // Generate Default PacketFilter config if not exist
+    if !file_exists(&pf_config_file) {
+        fs::write(
+            &pf_config_file,
+            serde_json::to_string(&PacketFilter::default()).unwrap(),
+        )
+        .unwrap();
+        //println!("[+] Created VOR PF config.")
+    } else {
+        //println!("[*] VOR PF config exists.");
+    }
+
+    let file_con = match fs::read_to_string(&pf_config_file) {
+        Ok(c) => c,
+        Err(_e) => {
+            //println!("[-] Could not parse bytes from file: {} [{}].. Skipping..", pf_config_file, _e);
+            std::process::exit(0);
+        }
+    };
+
+    match serde_json::from_str(&file_con) {
+        Ok(c) => c,
+        Err(_e) => {
+            //println!("[-] Failed to parse json from file: {} [{}]", pf_config_file, _e);
+            std::process::exit(0);
+        }
+    }
+}
+
+fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter, PacketFilter) {
     
     let mut configs = Vec::<VORConfigWrapper>::new();
 
@@ -149,12 +353,14 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
     let vor_config_file;
     let vor_app_configs_dir;
     let vor_pf_config_file;
+    let vor_vrc_pf_config_file;
 
     #[cfg(target_os = "windows")]
     {
         vor_config_file = format!("{}\\VORConfig.json", vor_root_dir);
         vor_app_configs_dir = format!("{}\\VORAppConfigs", vor_root_dir);
         vor_pf_config_file = format!("{}\\VOR_PF.json", vor_root_dir);
+        vor_vrc_pf_config_file = format!("{}\\VOR_VRC_PF.json", vor_root_dir);
     }
 
     #[cfg(target_os = "linux")]
@@ -162,6 +368,7 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
         vor_config_file = format!("{}/VORConfig.json", vor_root_dir);
         vor_app_configs_dir = format!("{}/VORAppConfigs", vor_root_dir);
         vor_pf_config_file = format!("{}/VOR_PF.json", vor_root_dir);
+        vor_vrc_pf_config_file = format!("{}/VOR_VRC_PF.json", vor_root_dir);
     }
 
     //If vor & vor config folder doesnt exist make it
@@ -191,26 +398,6 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
         //println!("[*] VOR router config exists.");
     }
 
-    // Generate Default PacketFilter config if not exist
-    if !file_exists(&vor_pf_config_file) {
-        fs::write(
-            &vor_pf_config_file,
-            serde_json::to_string(&PacketFilter {
-                enabled: false,
-                filter_bad_packets: false,
-                wl_enabled: false,
-                address_wl: vec![],
-                bl_enabled: false,
-                address_bl: vec![],
-            })
-            .unwrap(),
-        )
-        .unwrap();
-        //println!("[+] Created VOR PF config.")
-    } else {
-        //println!("[*] VOR PF config exists.");
-    }
-
     // Read VOR config
     let router_config = match fs::read_to_string(&vor_config_file) {
         Ok(c) => {
@@ -243,22 +430,9 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
         }
     };
 
-    // Read VOR PF config
-    let file_con = match fs::read_to_string(&vor_pf_config_file) {
-        Ok(c) => c,
-        Err(_e) => {
-            //println!("[-] Could not parse bytes from file: {} [{}].. Skipping..", vor_pf_config_file, _e);
-            std::process::exit(0);
-        }
-    };
-
-    let pf = match serde_json::from_str(&file_con) {
-        Ok(c) => c,
-        Err(_e) => {
-            //println!("[-] Failed to parse json from file: {} [{}]", vor_pf_config_file, _e);
-            std::process::exit(0);
-        }
-    };
+    // Read VOR PF configs
+    let pf = read_pf_config(vor_pf_config_file);
+    let vrc_pf = read_pf_config(vor_vrc_pf_config_file);
 
     // Read configs from folder
     let config_files =
@@ -285,6 +459,7 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
                 Ok(c) => configs.push(VORConfigWrapper {
                     config_data: c,
                     config_path: file_p,
+                    route_id: configs.len() as i64,
                 }),
                 Err(_e) => {
                     //println!("[-] Failed to parse json from file: {} [{}]", file_n, _e);
@@ -293,15 +468,16 @@ fn read_configs() -> (RouterConfig, Vec<VORConfigWrapper>, PacketFilter) {
             };
         }
     }
-    (router_config, configs, pf)
+    (router_config, configs, pf, vrc_pf)
 }
 
 pub fn config_construct() -> (
     RouterConfig,
     Vec<(VORConfigWrapper, VORAppStatus, AppConfigState)>,
     PacketFilter,
+    PacketFilter,
 ) {
-    let (vor_router_config, configs, pf) = read_configs();
+    let (vor_router_config, configs, pf, vrc_pf) = read_configs();
     /*
     if configs.len() < 1 {
         //println!("[?] Please put OSC application VOR configs in the [\\AppData\\Roaming\\VOR\\VORAppConfigs] directory.");
@@ -315,5 +491,5 @@ pub fn config_construct() -> (
     for c in configs {
         gconfs.push((c, VORAppStatus::Stopped, AppConfigState::SAVED));
     }
-    return (vor_router_config, gconfs, pf);
+    return (vor_router_config, gconfs, pf, vrc_pf);
 }
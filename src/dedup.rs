@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rosc::OscType;
+use serde::{Deserialize, Serialize};
+
+use crate::packet::OscBuf;
+use crate::vorutils::check_valid_u64;
+
+/*
+    Per-route deduplication config.
+    Messages with the same arguments as the last message sent for their address are skipped.
+*/
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Dedup {
+    // Resend an unchanged value if it wasn't sent for this many seconds. Empty or 0 disables the keepalive.
+    pub keepalive_secs: String,
+}
+
+impl Dedup {
+    pub fn is_valid(&self) -> bool {
+        check_valid_u64(&self.keepalive_secs)
+    }
+}
+
+pub struct RouteDedup {
+    keepalive: Option<Duration>,
+    // Last sent arguments for each address and when they were sent
+    last_sent: HashMap<String, (Vec<OscType>, Instant)>,
+}
+
+impl RouteDedup {
+    pub fn new(config: &Dedup) -> Self {
+        let keepalive = match config.keepalive_secs.parse::<u64>() {
+            Ok(s) if s > 0 => Some(Duration::from_secs(s)),
+            _ => None,
+        };
+
+        Self {
+            keepalive,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    // Returns false if the buffer is a message whose arguments didn't change
+    // Bundles and bad packets are always sent
+    pub fn check(&self, buf: &OscBuf) -> bool {
+        let msg = match buf.message() {
+            Some(msg) => msg,
+            None => return true,
+        };
+
+        match self.last_sent.get(&msg.addr) {
+            Some((args, sent_at)) => {
+                let keepalive_due = self.keepalive.is_some_and(|k| sent_at.elapsed() >= k);
+                *args != msg.args || keepalive_due
+            }
+            None => true,
+        }
+    }
+
+    // Only values the app received count as sent, so a value the rate limiter or a failed send dropped is sent again
+    pub fn mark_sent(&mut self, buf: &OscBuf) {
+        if let Some(msg) = buf.message() {
+            self.last_sent.insert(msg.addr.clone(), (msg.args.clone(), Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::{encoder, OscMessage, OscPacket};
+
+    fn buf(addr: &str, value: i32) -> OscBuf {
+        let bytes = encoder::encode(&OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Int(value)],
+        }))
+        .unwrap();
+        OscBuf::decode(&bytes)
+    }
+
+    fn dedup(keepalive_secs: &str) -> RouteDedup {
+        RouteDedup::new(&Dedup {
+            keepalive_secs: keepalive_secs.to_string(),
+        })
+    }
+
+    #[test]
+    fn suppresses_unchanged_values() {
+        let mut dedup = dedup("");
+        assert!(dedup.check(&buf("/a", 1)));
+        // Not sent yet, so it isn't a duplicate
+        assert!(dedup.check(&buf("/a", 1)));
+
+        dedup.mark_sent(&buf("/a", 1));
+        assert!(!dedup.check(&buf("/a", 1)));
+        assert!(dedup.check(&buf("/a", 2)));
+        assert!(dedup.check(&buf("/b", 1)));
+        // Bad packets are always sent
+        assert!(dedup.check(&OscBuf::decode(b"/bad\0")));
+    }
+
+    #[test]
+    fn resends_unchanged_value_after_keepalive() {
+        let mut dedup = dedup("5");
+        dedup.mark_sent(&buf("/a", 1));
+        assert!(!dedup.check(&buf("/a", 1)));
+
+        dedup.last_sent.get_mut("/a").unwrap().1 -= Duration::from_secs(5);
+        assert!(dedup.check(&buf("/a", 1)));
+        dedup.mark_sent(&buf("/a", 1));
+        assert!(!dedup.check(&buf("/a", 1)));
+    }
+}
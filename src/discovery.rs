@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+use crate::oscquery::{self, OSCQUERY_SERVICE, OSC_SERVICE};
+use crate::vorutils::is_local_ip;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// An OSC app advertised over mDNS on this machine
+#[derive(Debug, Clone)]
+pub struct DiscoveredApp {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    // Addresses the app's OSCQuery namespace says it accepts (empty for plain _osc._udp services)
+    pub addresses: Vec<String>,
+}
+
+/*
+    Browses mDNS for _osc._udp and _oscjson._tcp services.
+    OSCQuery services are queried for their OSC socket and namespace.
+    Apps are sent to the GUI as they resolve. Dropping stops browsing.
+*/
+pub struct Discovery {
+    mdns: ServiceDaemon,
+    apps_rx: Receiver<DiscoveredApp>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Discovery {
+    pub fn new() -> Result<Self, String> {
+        let mdns = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS daemon: {}", e))?;
+        let osc_rx = mdns
+            .browse(OSC_SERVICE)
+            .map_err(|e| format!("Failed to browse mDNS: {}", e))?;
+        let oscquery_rx = mdns
+            .browse(OSCQUERY_SERVICE)
+            .map_err(|e| format!("Failed to browse mDNS: {}", e))?;
+
+        let (apps_tx, apps_rx) = mpsc::channel();
+        Ok(Self {
+            mdns,
+            apps_rx,
+            thread: Some(thread::spawn(move || {
+                browse_services(osc_rx, oscquery_rx, apps_tx)
+            })),
+        })
+    }
+
+    // Apps resolved since the last call
+    pub fn poll(&self) -> Vec<DiscoveredApp> {
+        self.apps_rx.try_iter().collect()
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        // Shutting down the daemon disconnects the browse channels and ends the thread
+        let _ = self.mdns.shutdown();
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+fn browse_services(
+    osc_rx: mdns_sd::Receiver<ServiceEvent>,
+    oscquery_rx: mdns_sd::Receiver<ServiceEvent>,
+    apps_tx: Sender<DiscoveredApp>,
+) {
+    // Last app sent per service so re-announcements aren't listed twice
+    let mut seen: HashMap<String, (String, u16)> = HashMap::new();
+
+    loop {
+        let mut disconnected = 0;
+        for (rx, is_oscquery) in [(&osc_rx, false), (&oscquery_rx, true)] {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(ServiceEvent::ServiceResolved(info)) => {
+                    let app = if is_oscquery {
+                        oscquery_app(&info)
+                    } else {
+                        osc_app(&info)
+                    };
+                    if let Some(app) = app {
+                        let key = (app.host.clone(), app.port);
+                        if seen.get(info.get_fullname()) != Some(&key) {
+                            seen.insert(info.get_fullname().to_string(), key);
+                            if apps_tx.send(app).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                Ok(_event) => {}
+                Err(_e) if rx.is_disconnected() => disconnected += 1,
+                Err(_e) => {}
+            }
+        }
+        if disconnected == 2 {
+            return;
+        }
+    }
+}
+
+fn osc_app(info: &ServiceInfo) -> Option<DiscoveredApp> {
+    Some(DiscoveredApp {
+        name: instance_name(info),
+        host: local_address(info)?.to_string(),
+        port: info.get_port(),
+        addresses: Vec::new(),
+    })
+}
+
+// The OSC socket comes from HOST_INFO and the accepted addresses from the namespace
+fn oscquery_app(info: &ServiceInfo) -> Option<DiscoveredApp> {
+    let http_addr = SocketAddr::new(local_address(info)?, info.get_port());
+    let host_info = oscquery::query(http_addr, "/?HOST_INFO")?;
+
+    // Only UDP OSC servers can be routed to
+    if host_info["OSC_TRANSPORT"].as_str().is_some_and(|t| t != "UDP") {
+        return None;
+    }
+    let port = host_info["OSC_PORT"].as_u64().and_then(|p| u16::try_from(p).ok())?;
+    let host = match host_info["OSC_IP"].as_str().map(str::parse::<IpAddr>) {
+        Some(Ok(ip)) if !ip.is_unspecified() => ip,
+        _ => http_addr.ip(),
+    };
+    let name = match host_info["NAME"].as_str() {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => instance_name(info),
+    };
+    let addresses = oscquery::query(http_addr, "/")
+        .map(|root| oscquery::writable_addresses(&root))
+        .unwrap_or_default();
+
+    Some(DiscoveredApp {
+        name,
+        host: host.to_string(),
+        port,
+        addresses,
+    })
+}
+
+// "Name._osc._udp.local." -> "Name"
+fn instance_name(info: &ServiceInfo) -> String {
+    info.get_fullname()
+        .strip_suffix(info.get_type())
+        .unwrap_or(info.get_fullname())
+        .trim_end_matches('.')
+        .to_string()
+}
+
+// An advertised address that belongs to this machine. IPv4 is preferred.
+fn local_address(info: &ServiceInfo) -> Option<IpAddr> {
+    let mut local: Vec<IpAddr> = info
+        .get_addresses()
+        .iter()
+        .copied()
+        .filter(|ip| is_local_ip(*ip))
+        .collect();
+    local.sort_by_key(|ip| (ip.is_ipv6(), !ip.is_loopback()));
+    local.first().copied()
+}
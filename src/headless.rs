@@ -0,0 +1,110 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    config::{AppConfigState, RouterConfig, VORAppIdentifier, VORAppStatus, VORConfig, VORConfigWrapper},
+    pf::PacketFilter,
+    routing::{route_main, RouteEvent, RouterMsg, RouterOptions},
+};
+
+/*
+    Run the router without the GUI.
+    App statuses are printed to stdout and SIGINT/SIGTERM shuts the router down.
+*/
+pub fn headless_main(
+    vor_router_config: RouterConfig,
+    configs: Vec<(VORConfigWrapper, VORAppStatus, AppConfigState)>,
+    pf: PacketFilter,
+    vrc_pf: PacketFilter,
+    capture_path: Option<String>,
+) {
+    let app_names: Vec<(i64, String)> = configs
+        .iter()
+        .map(|c| (c.0.route_id, c.0.config_data.app_name.clone()))
+        .collect();
+    let confs: Vec<(VORConfig, i64)> = configs
+        .into_iter()
+        .map(|c| (c.0.config_data, c.0.route_id))
+        .collect();
+
+    let mut opts = match RouterOptions::new(&vor_router_config, pf, vrc_pf) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("[-] {}", e);
+            return;
+        }
+    };
+    opts.capture_path = capture_path;
+
+    let (router_tx, router_rx): (Sender<RouterMsg>, Receiver<RouterMsg>) = mpsc::channel();
+    let (app_stat_tx, app_stat_rx): (Sender<VORAppIdentifier>, Receiver<VORAppIdentifier>) =
+        mpsc::channel();
+    let (route_event_tx, route_event_rx): (Sender<RouteEvent>, Receiver<RouteEvent>) = mpsc::channel();
+
+    // SIGINT/SIGTERM -> ShutdownAll
+    if let Err(e) = ctrlc::set_handler(move || {
+        let _ = router_tx.send(RouterMsg::ShutdownAll);
+    }) {
+        println!("[-] Failed to set signal handler: {}", e);
+        return;
+    }
+
+    for (name, target) in &opts.listeners {
+        println!("[*] Starting VOR headless listener {} on {}", name, target);
+    }
+    if let Some(ref target) = opts.metrics_target {
+        println!("[*] Metrics endpoint on http://{}/metrics", target);
+    }
+
+    let router_thread = thread::spawn(move || route_main(opts, confs, router_rx, app_stat_tx, route_event_tx));
+
+    // Print app statuses until every route and the router have shut down
+    loop {
+        for event in route_event_rx.try_iter() {
+            print_route_event(event);
+        }
+        match app_stat_rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(status) => print_app_status(&app_names, status),
+            Err(RecvTimeoutError::Timeout) => {
+                if router_thread.is_finished() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    let _ = router_thread.join();
+    println!("[*] VOR stopped.");
+}
+
+fn print_route_event(event: RouteEvent) {
+    match event {
+        RouteEvent::Capture(s) => println!("[*] {}", s),
+        RouteEvent::ReturnError(Some(e)) => println!("[!] {}", e),
+        RouteEvent::ReturnError(None) => println!("[*] App replies are sent to VRChat again."),
+        // Traffic counters are only shown in the GUI
+        RouteEvent::RateLimit(..) => {}
+    }
+}
+
+fn print_app_status(app_names: &[(i64, String)], status: VORAppIdentifier) {
+    // Traffic counters are only shown in the GUI
+    if let VORAppStatus::Stats(_) | VORAppStatus::Listener(..) = status.status {
+        return;
+    }
+
+    if status.index == -1 {
+        match status.status {
+            VORAppStatus::AppError(e) => println!("[!] {}.. Not started!", e.msg),
+            _ => println!("[!] VOR failed to bind listener socket.. Not started!"),
+        }
+        return;
+    }
+
+    match app_names.iter().find(|(id, _)| *id == status.index) {
+        Some((_, name)) => println!("[{}] {}", name, status.status),
+        None => println!("[{}] {}", status.index, status.status),
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::vorutils::is_local_ip;
+
+// How often a route in health mode checks if its app is listening
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/*
+    App health of a UDP route with "Detect when the app is not listening" enabled.
+    The route's socket is connected to the app so ICMP port unreachable replies show up as ECONNREFUSED on the next send.
+    On Linux apps on this machine are also checked in /proc/net/udp, which notices an app closing or coming back without any sends.
+*/
+pub struct RouteHealth {
+    target: SocketAddr,
+    // The app runs on this machine so its socket shows up in /proc/net/udp
+    local: bool,
+    not_listening: bool,
+    last_refused: Option<Instant>,
+    // Set by a reply receiver thread that got the socket's refused error instead of the route
+    refused_reply: Arc<AtomicBool>,
+    next_check: Instant,
+}
+
+impl RouteHealth {
+    pub fn new(target: SocketAddr) -> Self {
+        Self {
+            target,
+            local: is_local_ip(target.ip()),
+            not_listening: false,
+            last_refused: None,
+            refused_reply: Arc::new(AtomicBool::new(false)),
+            next_check: Instant::now(),
+        }
+    }
+
+    // A refused send or receive on the route's socket means the app isn't listening. Returns Some(listening) if that changed.
+    pub fn socket_error(&mut self, e: &std::io::Error) -> Option<bool> {
+        if !is_refused(e) {
+            return None;
+        }
+        self.last_refused = Some(Instant::now());
+        self.set_listening(false)
+    }
+
+    // Flag for a reply receiver thread to set when it gets a refused error
+    pub fn refused_reply_flag(&self) -> Arc<AtomicBool> {
+        self.refused_reply.clone()
+    }
+
+    // Returns Some(listening) if that changed
+    pub fn check(&mut self) -> Option<bool> {
+        if self.refused_reply.swap(false, Ordering::Relaxed) {
+            self.last_refused = Some(Instant::now());
+            if let Some(changed) = self.set_listening(false) {
+                return Some(changed);
+            }
+        }
+
+        let now = Instant::now();
+        if now < self.next_check {
+            return None;
+        }
+        self.next_check = now + HEALTH_CHECK_INTERVAL;
+
+        match self.port_bound() {
+            Some(bound) => self.set_listening(bound),
+            // Without the socket table the app is listening again once sends stop being refused
+            None if self.not_listening => {
+                let refused_recently = self
+                    .last_refused
+                    .is_some_and(|t| now.duration_since(t) < HEALTH_CHECK_INTERVAL);
+                if refused_recently {
+                    None
+                } else {
+                    self.set_listening(true)
+                }
+            }
+            None => None,
+        }
+    }
+
+    pub fn next_check(&self) -> Instant {
+        self.next_check
+    }
+
+    fn set_listening(&mut self, listening: bool) -> Option<bool> {
+        if self.not_listening != listening {
+            return None;
+        }
+        self.not_listening = !listening;
+        Some(listening)
+    }
+
+    // None when it can't be checked (Not Linux or the app is on another machine)
+    fn port_bound(&self) -> Option<bool> {
+        if !self.local {
+            return None;
+        }
+        udp_port_bound(self.target)
+    }
+}
+
+pub fn is_refused(e: &std::io::Error) -> bool {
+    e.kind() == ErrorKind::ConnectionRefused
+}
+
+// Whether a UDP socket on this machine would receive packets sent to target
+#[cfg(target_os = "linux")]
+pub fn udp_port_bound(target: SocketAddr) -> Option<bool> {
+    let mut found = None;
+    for table in ["/proc/net/udp", "/proc/net/udp6"] {
+        if let Ok(t) = std::fs::read_to_string(table) {
+            if udp_table_has_target(&t, target) {
+                return Some(true);
+            }
+            found = Some(false);
+        }
+    }
+    found
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn udp_port_bound(_target: SocketAddr) -> Option<bool> {
+    None
+}
+
+/*
+    Search a /proc/net/udp(6) table for a socket bound to the target's port on its address or the wildcard address.
+    Local addresses look like 0100007F:2328 (IPv4) or 00000000000000000000000001000000:2328 (IPv6),
+    the address is printed as 32 bit words in host byte order.
+*/
+fn udp_table_has_target(table: &str, target: SocketAddr) -> bool {
+    table.lines().skip(1).any(|line| {
+        let local = match line.split_whitespace().nth(1) {
+            Some(l) => l,
+            None => return false,
+        };
+        let (ip, port) = match local.split_once(':') {
+            Some(l) => l,
+            None => return false,
+        };
+        if u16::from_str_radix(port, 16).ok() != Some(target.port()) {
+            return false;
+        }
+        match parse_proc_ip(ip) {
+            Some(ip) => ip.is_unspecified() || ip == target.ip() || ip == mapped_ip(target.ip()),
+            None => false,
+        }
+    })
+}
+
+fn parse_proc_ip(hex: &str) -> Option<IpAddr> {
+    let mut bytes = Vec::with_capacity(16);
+    for word in 0..hex.len() / 8 {
+        let w = u32::from_str_radix(hex.get(word * 8..word * 8 + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&w.to_ne_bytes());
+    }
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+// IPv4 targets are also received by dual stack sockets bound to their IPv4 mapped address
+fn mapped_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => IpAddr::V6(v4.to_ipv6_mapped()),
+        IpAddr::V6(_) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc_ip(ip: IpAddr) -> String {
+        let octets = match ip {
+            IpAddr::V4(v4) => v4.octets().to_vec(),
+            IpAddr::V6(v6) => v6.octets().to_vec(),
+        };
+        octets
+            .chunks(4)
+            .map(|w| format!("{:08X}", u32::from_ne_bytes(w.try_into().unwrap())))
+            .collect()
+    }
+
+    fn table(sockets: &[(IpAddr, u16)]) -> String {
+        let mut t = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops\n".to_string();
+        for (i, (ip, port)) in sockets.iter().enumerate() {
+            t.push_str(&format!(
+                "  {}: {}:{:04X} 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 1234 2 0000000000000000 0\n",
+                i,
+                proc_ip(*ip),
+                port
+            ));
+        }
+        t
+    }
+
+    #[test]
+    fn finds_sockets_bound_to_target() {
+        let target: SocketAddr = "127.0.0.1:9100".parse().unwrap();
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        assert!(udp_table_has_target(&table(&[(localhost, 9100)]), target));
+        assert!(udp_table_has_target(&table(&[(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 9100)]), target));
+        assert!(udp_table_has_target(&table(&[(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 9100)]), target));
+        assert!(!udp_table_has_target(&table(&[(localhost, 9101)]), target));
+        assert!(!udp_table_has_target(&table(&[(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), 9100)]), target));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn proc_net_udp_shows_bound_socket() {
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = sock.local_addr().unwrap();
+        assert_eq!(udp_port_bound(target), Some(true));
+        drop(sock);
+        assert_eq!(udp_port_bound(target), Some(false));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn refused_send_marks_app_not_listening() {
+        let target = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        sock.connect(target).unwrap();
+        let mut health = RouteHealth::new(target);
+
+        // Loopback port unreachable replies fail the next send on the connected socket
+        let refused = (0..10).find_map(|_| sock.send(b"/vor").err()).unwrap();
+        assert_eq!(health.socket_error(&refused), Some(false));
+        assert_eq!(health.socket_error(&refused), None);
+    }
+}
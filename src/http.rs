@@ -0,0 +1,103 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/*
+    Minimal HTTP/1.1 server for VOR's local endpoints (OSCQuery, metrics).
+    GET requests are answered one at a time on the server thread and every response closes the connection.
+    The handler gets the request target (ex: "/avatar?HOST_INFO") and returns the content type and body, or None for 404.
+*/
+pub struct HttpServer {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl HttpServer {
+    pub fn new<F>(listener: TcpListener, handler: F) -> std::io::Result<Self>
+    where
+        F: Fn(&str) -> Option<(&'static str, String)> + Send + 'static,
+    {
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        Ok(Self {
+            stop,
+            thread: Some(thread::spawn(move || {
+                while !thread_stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _addr)) => handle_request(stream, &handler),
+                        Err(_e) => thread::sleep(POLL_INTERVAL),
+                    }
+                }
+            })),
+        })
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+fn handle_request<F>(mut stream: TcpStream, handler: &F)
+where
+    F: Fn(&str) -> Option<(&'static str, String)>,
+{
+    if stream.set_nonblocking(false).is_err()
+        || stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err()
+        || stream.set_write_timeout(Some(REQUEST_TIMEOUT)).is_err()
+    {
+        return;
+    }
+
+    let target = match read_request_target(&mut stream) {
+        Some(t) => t,
+        None => return,
+    };
+
+    let (status, content_type, body) = match handler(&target) {
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None => ("404 Not Found", "text/plain", String::new()),
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.flush();
+}
+
+// Request target of a GET request
+fn read_request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut req = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).ok()?;
+        if n == 0 || req.len() + n > MAX_REQUEST_SIZE {
+            return None;
+        }
+        req.extend_from_slice(&buf[..n]);
+    }
+
+    let req = String::from_utf8_lossy(&req);
+    let mut request_line = req.lines().next()?.split_whitespace();
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    }
+}
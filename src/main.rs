@@ -2,20 +2,39 @@
 
 use eframe::{egui::Vec2, run_native, NativeOptions};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
+mod bench;
+mod capture;
 mod config;
+mod dedup;
+mod discovery;
+mod headless;
+mod health;
+mod http;
+mod metrics;
+mod oscquery;
+mod packet;
 mod routedbg;
 mod routing;
+mod stats;
+mod supervisor;
 mod pf;
+mod queue;
+mod ratelimit;
+mod replay;
+mod rewrite;
+mod transport;
 //mod management;
 mod ui;
 //mod vodrp;
 mod vorerr;
 mod vorupdate;
 mod vorutils;
+mod websocket;
 
 use config::config_construct;
+use replay::ReplayOptions;
 use ui::VORGUI;
 
 #[derive(Parser, Debug)]
@@ -23,6 +42,50 @@ use ui::VORGUI;
 pub struct VCArgs {
     #[clap(short, long)]
     pub enable_on_start: bool,
+    /// Run the router without the GUI
+    #[clap(long)]
+    pub headless: bool,
+    /// Record the listeners' datagrams to FILE while the router runs (pcapng if FILE ends in .pcapng or .pcap, native format otherwise)
+    #[clap(long, value_name = "FILE")]
+    pub capture: Option<String>,
+    #[clap(subcommand)]
+    pub command: Option<VCCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VCCommand {
+    /// Compare idle CPU and routing latency of the sync and async routing modes
+    Bench {
+        /// App routes per mode
+        #[clap(long, default_value_t = 4)]
+        routes: usize,
+        /// Packets sent to the router per mode
+        #[clap(long, default_value_t = 1000)]
+        packets: usize,
+    },
+    /// Replay a capture file into the router's listeners or straight to an app
+    Replay {
+        /// Capture file (native, pcapng or pcap)
+        file: String,
+        /// Playback speed multiplier (0 sends as fast as possible)
+        #[clap(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Start over at the end until stopped with Ctrl+C
+        #[clap(long = "loop")]
+        looped: bool,
+        /// Skip packets captured less than this many seconds after the first packet
+        #[clap(long)]
+        from: Option<f64>,
+        /// Skip packets captured more than this many seconds after the first packet
+        #[clap(long)]
+        to: Option<f64>,
+        /// Only replay messages with this OSC address (Repeat for more addresses)
+        #[clap(long = "address", value_name = "ADDRESS")]
+        addresses: Vec<String>,
+        /// Send to this app (Its app name) instead of the listener each packet was captured on
+        #[clap(long, value_name = "APP")]
+        route: Option<String>,
+    },
 }
 
 fn parse_args() -> VCArgs {
@@ -31,8 +94,30 @@ fn parse_args() -> VCArgs {
 
 fn main() {
     let args = parse_args();
+    if let Some(VCCommand::Bench { routes, packets }) = args.command {
+        bench::bench_main(routes, packets);
+        return;
+    }
     //println!("Enable On Start: {}", args.enable_on_start);
-    let (vor_router_config, configs, pf) = config_construct();
+    let (vor_router_config, configs, pf, vrc_pf) = config_construct();
+
+    if let Some(VCCommand::Replay { file, speed, looped, from, to, addresses, route }) = args.command {
+        let opts = match ReplayOptions::new(speed, looped, from, to, addresses) {
+            Ok(o) => o,
+            Err(e) => {
+                println!("[-] {}", e);
+                return;
+            }
+        };
+        let configs = configs.into_iter().map(|c| c.0.config_data).collect();
+        replay::replay_main(file, route, opts, vor_router_config, configs);
+        return;
+    }
+
+    if args.headless {
+        headless::headless_main(vor_router_config, configs, pf, vrc_pf, args.capture);
+        return;
+    }
 
     let mut native_opts = NativeOptions::default();
     native_opts.initial_window_size = Some(Vec2::new(700., 750.));
@@ -40,6 +125,6 @@ fn main() {
     run_native(
         "VRChat OSC Router",
         native_opts,
-        Box::new(|cc| Box::new(VORGUI::new(cc, args, configs, vor_router_config, pf))),
+        Box::new(|cc| Box::new(VORGUI::new(cc, args, configs, vor_router_config, pf, vrc_pf))),
     );
 }
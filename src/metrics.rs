@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+
+use crate::config::{VORAppIdentifier, VORAppStatus};
+use crate::http::HttpServer;
+use crate::routing::RouteEvent;
+use crate::ratelimit::RateLimitStats;
+use crate::stats::{ListenerStats, RouteStats, LATENCY_BUCKETS};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// States of the vor_route_status stateset
+const ROUTE_STATES: [&str; 8] = ["running", "paused", "stopped", "connecting", "disconnected", "not_listening", "restarting", "error"];
+
+#[derive(Default)]
+struct RouteMetrics {
+    app: String,
+    state: &'static str,
+    clients: Option<usize>,
+    warning: bool,
+    // Kept when the route is restarted
+    restarts: u64,
+    stats: RouteStats,
+    rate_limit: RateLimitStats,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    routes: BTreeMap<i64, RouteMetrics>,
+    // Listeners in the order they were first reported
+    listeners: Vec<(String, ListenerStats)>,
+}
+
+/*
+    Router metrics shared by the router and the metrics endpoint.
+    Built from the statuses routes and listeners send over the status channel.
+*/
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<MetricsState>>);
+
+impl Metrics {
+    // A route was (re)started. Its counters start over, except restarts.
+    pub fn add_route(&self, id: i64, app: &str) {
+        let mut state = self.0.lock().unwrap();
+        let restarts = state.routes.get(&id).map_or(0, |r| r.restarts);
+        state.routes.insert(
+            id,
+            RouteMetrics {
+                app: app.to_string(),
+                state: "stopped",
+                restarts,
+                ..Default::default()
+            },
+        );
+    }
+
+    pub fn remove_route(&self, id: i64) {
+        self.0.lock().unwrap().routes.remove(&id);
+    }
+
+    pub fn record(&self, status: &VORAppIdentifier) {
+        let mut state = self.0.lock().unwrap();
+        if let VORAppStatus::Listener(ref name, stats) = status.status {
+            match state.listeners.iter_mut().find(|l| l.0 == *name) {
+                Some(l) => l.1 = stats,
+                None => state.listeners.push((name.clone(), stats)),
+            }
+            return;
+        }
+
+        let route = match state.routes.get_mut(&status.index) {
+            Some(r) => r,
+            None => return,
+        };
+        match status.status {
+            VORAppStatus::Stats(stats) => route.stats = stats,
+            VORAppStatus::Clients(n) => {
+                route.state = "running";
+                route.clients = Some(n);
+            }
+            VORAppStatus::Running => route.state = "running",
+            VORAppStatus::Paused => route.state = "paused",
+            VORAppStatus::Stopped | VORAppStatus::Disabled => route.state = "stopped",
+            VORAppStatus::Connecting => route.state = "connecting",
+            VORAppStatus::Disconnected(_) => route.state = "disconnected",
+            VORAppStatus::NotListening => route.state = "not_listening",
+            VORAppStatus::AppError(_) => route.state = "error",
+            VORAppStatus::Restarting(ref s) => {
+                route.state = "restarting";
+                route.restarts = s.restarts;
+            }
+            VORAppStatus::Warning(ref w) => route.warning = w.is_some(),
+            VORAppStatus::Listener(..) => {}
+        }
+    }
+
+    pub fn record_event(&self, event: &RouteEvent) {
+        let mut state = self.0.lock().unwrap();
+        match *event {
+            RouteEvent::RateLimit(id, stats) => {
+                if let Some(route) = state.routes.get_mut(&id) {
+                    route.rate_limit = stats;
+                }
+            }
+            RouteEvent::Capture(_) | RouteEvent::ReturnError(_) => {}
+        }
+    }
+
+    // OpenMetrics text exposition
+    pub fn render(&self) -> String {
+        let state = self.0.lock().unwrap();
+        let mut out = String::new();
+
+        let listener_counter = |out: &mut String, name: &str, help: &str, value: fn(&ListenerStats) -> u64| {
+            family(out, name, "counter", help);
+            for (listener, stats) in &state.listeners {
+                let _ = writeln!(out, "{}_total{{listener=\"{}\"}} {}", name, escape(listener), value(stats));
+            }
+        };
+        listener_counter(&mut out, "vor_listener_received_packets", "Packets received by the listener.", |s| s.received);
+        listener_counter(&mut out, "vor_listener_allowed_packets", "Packets allowed by the packet filter.", |s| s.allowed);
+        listener_counter(&mut out, "vor_listener_malformed_packets", "Packets that could not be parsed as OSC.", |s| s.malformed);
+        family(&mut out, "vor_listener_dropped_packets", "counter", "Packets dropped by the packet filter.");
+        for (listener, stats) in &state.listeners {
+            let listener = escape(listener);
+            let _ = writeln!(out, "vor_listener_dropped_packets_total{{listener=\"{}\",reason=\"address\"}} {}", listener, stats.dropped - stats.dropped_malformed);
+            let _ = writeln!(out, "vor_listener_dropped_packets_total{{listener=\"{}\",reason=\"bad_packet\"}} {}", listener, stats.dropped_malformed);
+        }
+
+        let route_counter = |out: &mut String, name: &str, help: &str, value: fn(&RouteMetrics) -> u64| {
+            family(out, name, "counter", help);
+            for route in state.routes.values() {
+                let _ = writeln!(out, "{}_total{{app=\"{}\"}} {}", name, escape(&route.app), value(route));
+            }
+        };
+        route_counter(&mut out, "vor_route_received_packets", "Packets the route received from the listeners.", |r| r.stats.received);
+        route_counter(&mut out, "vor_route_sent_packets", "Packets sent to the app.", |r| r.stats.packets);
+        route_counter(&mut out, "vor_route_sent_bytes", "Bytes sent to the app.", |r| r.stats.bytes);
+        route_counter(&mut out, "vor_route_send_errors", "Failed sends to the app.", |r| r.stats.send_errors);
+        route_counter(&mut out, "vor_route_restarts", "Times the route failed and was restarted.", |r| r.restarts);
+        route_counter(
+            &mut out,
+            "vor_route_lagged_packets",
+            "Packets lost because the route fell behind the listeners (Queue full or coalesced).",
+            |r| r.stats.queue_dropped + r.stats.queue_coalesced,
+        );
+        family(&mut out, "vor_route_dropped_packets", "counter", "Packets the route did not send to the app.");
+        for route in state.routes.values() {
+            let app = escape(&route.app);
+            for (reason, value) in [
+                ("route_filter", route.stats.filtered),
+                ("unchanged", route.stats.unchanged),
+                ("rate_limit", route.rate_limit.dropped),
+                ("coalesced", route.rate_limit.coalesced),
+                ("queue_full", route.stats.queue_dropped),
+                ("queue_coalesced", route.stats.queue_coalesced),
+            ] {
+                let _ = writeln!(out, "vor_route_dropped_packets_total{{app=\"{}\",reason=\"{}\"}} {}", app, reason, value);
+            }
+        }
+
+        family(&mut out, "vor_route_status", "stateset", "Route status.");
+        for route in state.routes.values() {
+            let app = escape(&route.app);
+            for s in ROUTE_STATES {
+                let _ = writeln!(out, "vor_route_status{{app=\"{}\",vor_route_status=\"{}\"}} {}", app, s, (route.state == s) as u8);
+            }
+        }
+
+        family(&mut out, "vor_route_queue_warning", "gauge", "1 while the route's queue is over its high-water mark.");
+        for route in state.routes.values() {
+            let _ = writeln!(out, "vor_route_queue_warning{{app=\"{}\"}} {}", escape(&route.app), route.warning as u8);
+        }
+
+        family(&mut out, "vor_route_websocket_clients", "gauge", "Clients connected to a WebSocket route.");
+        for route in state.routes.values() {
+            if let Some(n) = route.clients {
+                let _ = writeln!(out, "vor_route_websocket_clients{{app=\"{}\"}} {}", escape(&route.app), n);
+            }
+        }
+
+        family(&mut out, "vor_route_latency_seconds", "histogram", "Time from a listener receiving a packet to the route sending it.");
+        for route in state.routes.values() {
+            let app = escape(&route.app);
+            let latency = &route.stats.latency;
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(latency.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(out, "vor_route_latency_seconds_bucket{{app=\"{}\",le=\"{}\"}} {}", app, le, cumulative);
+            }
+            let _ = writeln!(out, "vor_route_latency_seconds_bucket{{app=\"{}\",le=\"+Inf\"}} {}", app, latency.count);
+            let _ = writeln!(out, "vor_route_latency_seconds_count{{app=\"{}\"}} {}", app, latency.count);
+            let _ = writeln!(out, "vor_route_latency_seconds_sum{{app=\"{}\"}} {}", app, latency.sum);
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Serves /metrics until dropped
+pub struct MetricsServer {
+    _http: HttpServer,
+}
+
+impl MetricsServer {
+    pub fn new(bind_addr: SocketAddr, metrics: Metrics) -> Result<Self, String> {
+        let listener = TcpListener::bind(bind_addr)
+            .map_err(|e| format!("Failed to bind metrics endpoint: {}", e))?;
+        let http = HttpServer::new(listener, move |target| match target {
+            "/metrics" => Some((CONTENT_TYPE, metrics.render())),
+            _ => None,
+        })
+        .map_err(|e| format!("Failed to start metrics endpoint: {}", e))?;
+        Ok(Self { _http: http })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::LatencyHistogram;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_route_and_listener_metrics() {
+        let metrics = Metrics::default();
+        metrics.add_route(0, "Face \"Tracker\"");
+        metrics.add_route(1, "Haptics");
+
+        let mut latency = LatencyHistogram::default();
+        latency.observe(Duration::from_micros(80));
+        latency.observe(Duration::from_millis(2));
+        let mut listener = ListenerStats::default();
+        listener.count(true, false);
+        listener.count(false, true);
+        listener.count(false, false);
+        for status in [
+            VORAppStatus::Running,
+            VORAppStatus::Stats(RouteStats {
+                received: 3,
+                packets: 2,
+                bytes: 64,
+                queue_dropped: 1,
+                queue_coalesced: 2,
+                latency,
+                ..Default::default()
+            }),
+            VORAppStatus::Listener("VRChat".to_string(), listener),
+        ] {
+            metrics.record(&VORAppIdentifier { index: 0, status });
+        }
+        metrics.record(&VORAppIdentifier {
+            index: 1,
+            status: VORAppStatus::Paused,
+        });
+
+        let text = metrics.render();
+        assert!(text.ends_with("# EOF\n"));
+        assert!(text.contains("vor_listener_received_packets_total{listener=\"VRChat\"} 3\n"));
+        assert!(text.contains("vor_listener_dropped_packets_total{listener=\"VRChat\",reason=\"address\"} 1\n"));
+        assert!(text.contains("vor_listener_dropped_packets_total{listener=\"VRChat\",reason=\"bad_packet\"} 1\n"));
+        assert!(text.contains("vor_route_sent_packets_total{app=\"Face \\\"Tracker\\\"\"} 2\n"));
+        assert!(text.contains("vor_route_lagged_packets_total{app=\"Face \\\"Tracker\\\"\"} 3\n"));
+        assert!(text.contains("vor_route_status{app=\"Face \\\"Tracker\\\"\",vor_route_status=\"running\"} 1\n"));
+        assert!(text.contains("vor_route_status{app=\"Haptics\",vor_route_status=\"paused\"} 1\n"));
+        assert!(text.contains("vor_route_status{app=\"Haptics\",vor_route_status=\"running\"} 0\n"));
+        assert!(text.contains("vor_route_latency_seconds_bucket{app=\"Face \\\"Tracker\\\"\",le=\"0.0001\"} 1\n"));
+        assert!(text.contains("vor_route_latency_seconds_bucket{app=\"Face \\\"Tracker\\\"\",le=\"0.0025\"} 2\n"));
+        assert!(text.contains("vor_route_latency_seconds_count{app=\"Haptics\"} 0\n"));
+    }
+}
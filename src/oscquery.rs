@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde_json::{json, Map, Value};
+
+use crate::http::HttpServer;
+use crate::pf::PacketFilter;
+
+pub const OSCQUERY_SERVICE: &str = "_oscjson._tcp.local.";
+pub const OSC_SERVICE: &str = "_osc._udp.local.";
+const SERVICE_NAME: &str = "VOR";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RESPONSE_SIZE: u64 = 4 * 1024 * 1024;
+
+// OSCQuery ACCESS values
+const ACCESS_NONE: u8 = 0;
+const ACCESS_WRITE: u8 = 2;
+const ACCESS_READ_WRITE: u8 = 3;
+
+/*
+    OSCQuery server for the router's listener socket.
+    Serves HOST_INFO and the namespace over HTTP and advertises both the HTTP
+    server (_oscjson._tcp) and the OSC socket (_osc._udp) over mDNS so VRChat
+    finds VOR without a fixed port.
+*/
+pub struct OscQueryServer {
+    _http: HttpServer,
+    mdns: ServiceDaemon,
+    services: Vec<String>,
+}
+
+impl OscQueryServer {
+    pub fn new(osc_addr: SocketAddr, pf: &PacketFilter) -> Result<Self, String> {
+        // HTTP server on the listener's IP with an OS assigned port
+        let listener = TcpListener::bind(SocketAddr::new(osc_addr.ip(), 0))
+            .map_err(|e| format!("Failed to bind OSCQuery HTTP server: {}", e))?;
+        let http_port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let host_info = host_info(osc_addr);
+        let namespace = Namespace::from_pf(pf);
+        let http = HttpServer::new(listener, move |target| {
+            respond(target, &host_info, &namespace).map(|v| ("application/json", v.to_string()))
+        })
+        .map_err(|e| format!("Failed to start OSCQuery HTTP server: {}", e))?;
+
+        let mdns = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS daemon: {}", e))?;
+        let mut services = Vec::new();
+        for (ty_domain, port) in [(OSCQUERY_SERVICE, http_port), (OSC_SERVICE, osc_addr.port())] {
+            let info = service_info(ty_domain, osc_addr.ip(), port)
+                .map_err(|e| format!("Failed to register mDNS service: {}", e))?;
+            services.push(info.get_fullname().to_string());
+            mdns.register(info)
+                .map_err(|e| format!("Failed to register mDNS service: {}", e))?;
+        }
+
+        Ok(Self {
+            _http: http,
+            mdns,
+            services,
+        })
+    }
+}
+
+impl Drop for OscQueryServer {
+    fn drop(&mut self) {
+        // Send mDNS goodbyes before stopping the daemon
+        for fullname in &self.services {
+            if let Ok(rx) = self.mdns.unregister(fullname) {
+                let _ = rx.recv_timeout(REQUEST_TIMEOUT);
+            }
+        }
+        let _ = self.mdns.shutdown();
+    }
+}
+
+fn service_info(ty_domain: &str, ip: IpAddr, port: u16) -> Result<ServiceInfo, mdns_sd::Error> {
+    let host_name = format!("vor-{}.local.", port);
+    if ip.is_unspecified() {
+        // Listening on every interface, advertise all of the host's addresses
+        Ok(ServiceInfo::new(ty_domain, SERVICE_NAME, &host_name, (), port, None)?.enable_addr_auto())
+    } else {
+        ServiceInfo::new(ty_domain, SERVICE_NAME, &host_name, ip, port, None)
+    }
+}
+
+fn host_info(osc_addr: SocketAddr) -> Value {
+    let osc_ip = if osc_addr.ip().is_unspecified() {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    } else {
+        osc_addr.ip()
+    };
+
+    json!({
+        "NAME": SERVICE_NAME,
+        "EXTENSIONS": {
+            "ACCESS": true,
+            "VALUE": false,
+            "TYPE": false,
+            "DESCRIPTION": false,
+        },
+        "OSC_IP": osc_ip.to_string(),
+        "OSC_PORT": osc_addr.port(),
+        "OSC_TRANSPORT": "UDP",
+    })
+}
+
+/*
+    OSCQuery namespace.
+    /avatar is always advertised so VRChat sends to VOR.
+    With the packet filter's whitelist enabled the whitelisted addresses are merged in as leaves.
+*/
+#[derive(Debug, Default)]
+struct Namespace {
+    contents: BTreeMap<String, Namespace>,
+}
+
+impl Namespace {
+    fn from_pf(pf: &PacketFilter) -> Self {
+        let mut root = Namespace::default();
+        root.insert("/avatar");
+        if pf.enabled && pf.wl_enabled {
+            for (addr, _) in &pf.address_wl {
+                root.insert(addr);
+            }
+        }
+        root
+    }
+
+    fn insert(&mut self, address: &str) {
+        let mut node = self;
+        for part in address.split('/').filter(|p| !p.is_empty()) {
+            node = node.contents.entry(part.to_string()).or_default();
+        }
+    }
+
+    fn find(&self, path: &str) -> Option<&Namespace> {
+        let mut node = self;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            node = node.contents.get(part)?;
+        }
+        Some(node)
+    }
+
+    fn to_json(&self, full_path: &str) -> Value {
+        let mut node = Map::new();
+        node.insert("FULL_PATH".to_string(), json!(full_path));
+        if self.contents.is_empty() {
+            // Leaf: an address VOR accepts
+            node.insert("ACCESS".to_string(), json!(ACCESS_WRITE));
+        } else {
+            node.insert("ACCESS".to_string(), json!(ACCESS_NONE));
+            let contents: Map<String, Value> = self
+                .contents
+                .iter()
+                .map(|(name, child)| {
+                    let child_path = format!("{}/{}", full_path.trim_end_matches('/'), name);
+                    (name.clone(), child.to_json(&child_path))
+                })
+                .collect();
+            node.insert("CONTENTS".to_string(), Value::Object(contents));
+        }
+        Value::Object(node)
+    }
+}
+
+fn respond(target: &str, host_info: &Value, namespace: &Namespace) -> Option<Value> {
+    let (path, query) = match target.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (target, None),
+    };
+
+    if query == Some("HOST_INFO") {
+        return Some(host_info.clone());
+    }
+
+    let full_path = if path.len() > 1 { path.trim_end_matches('/') } else { "/" };
+    let node = namespace.find(full_path)?.to_json(full_path);
+    match query {
+        // Single attribute, ex: /avatar?ACCESS
+        Some(attr) => node.get(attr).map(|v| json!({ attr: v })),
+        None => Some(node),
+    }
+}
+
+// GET a JSON document from another OSCQuery server, ex: query(addr, "/?HOST_INFO")
+pub fn query(addr: SocketAddr, target: &str) -> Option<Value> {
+    let mut stream = TcpStream::connect_timeout(&addr, REQUEST_TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target, addr
+    )
+    .ok()?;
+
+    let mut resp = Vec::new();
+    stream.take(MAX_RESPONSE_SIZE).read_to_end(&mut resp).ok()?;
+    let resp = String::from_utf8_lossy(&resp);
+    let (head, body) = resp.split_once("\r\n\r\n")?;
+    if head.split_whitespace().nth(1) != Some("200") {
+        return None;
+    }
+    serde_json::from_str(body).ok()
+}
+
+// Leaf addresses of a namespace node that accept OSC messages
+pub fn writable_addresses(node: &Value) -> Vec<String> {
+    let mut addresses = Vec::new();
+    collect_writable(node, &mut addresses);
+    addresses
+}
+
+fn collect_writable(node: &Value, addresses: &mut Vec<String>) {
+    if let Some(contents) = node["CONTENTS"].as_object() {
+        for child in contents.values() {
+            collect_writable(child, addresses);
+        }
+        return;
+    }
+
+    let access = node["ACCESS"].as_u64().unwrap_or(ACCESS_NONE as u64);
+    if access == ACCESS_WRITE as u64 || access == ACCESS_READ_WRITE as u64 {
+        if let Some(path) = node["FULL_PATH"].as_str() {
+            addresses.push(path.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn whitelist_pf(addrs: &[&str]) -> PacketFilter {
+        PacketFilter {
+            enabled: true,
+            wl_enabled: true,
+            address_wl: addrs.iter().map(|a| (a.to_string(), false)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn namespace_merges_whitelisted_addresses() {
+        let ns = Namespace::from_pf(&whitelist_pf(&[
+            "/avatar/parameters/Foo",
+            "/avatar/parameters/Bar",
+            "/chatbox/input",
+        ]));
+        let host_info = host_info("127.0.0.1:9001".parse().unwrap());
+
+        let root = respond("/", &host_info, &ns).unwrap();
+        assert_eq!(root["ACCESS"], ACCESS_NONE);
+        assert!(root["CONTENTS"]["chatbox"]["CONTENTS"]["input"].is_object());
+
+        let params = &root["CONTENTS"]["avatar"]["CONTENTS"]["parameters"];
+        assert_eq!(params["FULL_PATH"], "/avatar/parameters");
+        assert_eq!(params["CONTENTS"]["Foo"]["FULL_PATH"], "/avatar/parameters/Foo");
+        assert_eq!(params["CONTENTS"]["Foo"]["ACCESS"], ACCESS_WRITE);
+
+        assert_eq!(
+            respond("/avatar/parameters/Bar?ACCESS", &host_info, &ns).unwrap(),
+            json!({"ACCESS": ACCESS_WRITE})
+        );
+        assert!(respond("/avatar/parameters/Baz", &host_info, &ns).is_none());
+
+        let mut addresses = writable_addresses(&root);
+        addresses.sort();
+        assert_eq!(
+            addresses,
+            vec!["/avatar/parameters/Bar", "/avatar/parameters/Foo", "/chatbox/input"]
+        );
+    }
+
+    #[test]
+    fn host_info_reports_listener_socket() {
+        let ns = Namespace::from_pf(&PacketFilter::default());
+        let host_info = host_info("0.0.0.0:9011".parse().unwrap());
+
+        let info = respond("/?HOST_INFO", &host_info, &ns).unwrap();
+        assert_eq!(info["OSC_IP"], "127.0.0.1");
+        assert_eq!(info["OSC_PORT"], 9011);
+        assert_eq!(info["OSC_TRANSPORT"], "UDP");
+
+        // Without a whitelist only /avatar is advertised
+        let root = respond("/", &host_info, &ns).unwrap();
+        assert_eq!(root["CONTENTS"].as_object().unwrap().len(), 1);
+        assert_eq!(root["CONTENTS"]["avatar"]["ACCESS"], ACCESS_WRITE);
+    }
+}
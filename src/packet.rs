@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use rosc::{encoder, OscMessage, OscPacket};
+
+/*
+    An OSC packet as it moves through the router.
+    bytes are exactly the bytes to send (never padded to the MTU) and pkt is the packet they decode to, or None if they aren't valid OSC.
+    Listeners decode once and the routes share the result through an Arc.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscBuf {
+    pub bytes: Vec<u8>,
+    pub pkt: Option<OscPacket>,
+}
+
+pub type SharedOscBuf = Arc<OscBuf>;
+
+impl OscBuf {
+    // Received bytes
+    pub fn decode(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            pkt: rosc::decoder::decode_udp(bytes).ok().map(|p| p.1),
+        }
+    }
+
+    // A packet VOR built or changed (filtered bundle, rewritten address)
+    pub fn encode(pkt: OscPacket) -> Option<Self> {
+        let bytes = encoder::encode(&pkt).ok()?;
+        Some(Self {
+            bytes,
+            pkt: Some(pkt),
+        })
+    }
+
+    pub fn message(&self) -> Option<&OscMessage> {
+        match self.pkt {
+            Some(OscPacket::Message(ref msg)) => Some(msg),
+            _ => None,
+        }
+    }
+}
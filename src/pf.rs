@@ -1,12 +1,14 @@
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 
-use rosc::{OscPacket, decoder::MTU, encoder};
+use rosc::{OscBundle, OscPacket};
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast::Sender as bcst_Sender;
 
+use crate::packet::{OscBuf, SharedOscBuf};
 use crate::routedbg;
+use crate::stats::ListenerStats;
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct PacketFilter {
     pub enabled: bool,
     pub filter_bad_packets: bool,
@@ -16,190 +18,267 @@ pub struct PacketFilter {
     pub bl_enabled: bool,
     //pub bl_editing: bool,
     pub address_bl: Vec<(String, bool)>,
+    #[serde(default)]
+    pub bundle_mode: BundleMode,
 }
 
-#[inline]
-pub fn packet_filter(
-    pf: &PacketFilter,
-    buf: [u8; MTU],
-    address: &String,
-    bcst_tx: &bcst_Sender<Vec<u8>>,
-    debug_sender: &Option<Sender<routedbg::DebugPacket>>
-) {
-    let pf_wl: Vec<String> = pf.address_wl.iter().map(|i| i.0.clone()).collect();
-    let pf_bl: Vec<String> = pf.address_bl.iter().map(|i| i.0.clone()).collect();
+// What to forward when only some messages in a bundle pass the filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum BundleMode {
+    // Rebuild the bundle with only the surviving messages
+    #[default]
+    Rebuild,
+    // Forward the surviving messages as individual packets
+    Flatten,
+}
 
-    if pf.wl_enabled {
-        // Whitelist
-        match rosc::decoder::decode_udp(&buf) {
-            Ok(ref pkt) => {
-                if let OscPacket::Message(msg) = &pkt.1 {
-                    if pf_wl.contains(&msg.addr) {
-                        // Here sending the decoded packet's buffer instead of the UDP buffer
-                        // because some OSC libraries cant parse OSC packets with trailing NULL bytes.
-                        // However if malformed OSC packets are relayed due to PF allowing bad packets through they will be sent with a full MTU (NULL BYTES PADDED)
-                        let encoded_packet_buf = encoder::encode(&pkt.1).unwrap();
-                        bcst_tx.send(encoded_packet_buf).unwrap();
-                        if let Some(ref dbgs) = debug_sender {
-                            routedbg::send_indbg_packet(
-                                dbgs,
-                                &buf,
-                                Some(pkt.1.clone()),
-                                address.to_string(),
-                                routedbg::IncomingDebugMode::ALLOWED,
-                            );
-                        }
-                    } else {
-                        if let Some(ref dbgs) = debug_sender {
-                            routedbg::send_indbg_packet(
-                                dbgs,
-                                &buf,
-                                Some(pkt.1.clone()),
-                                address.to_string(),
-                                routedbg::IncomingDebugMode::DROPPED,
-                            );
-                        }
-                    }
-                }
-            }
-            Err(_e) => {
-                if !pf.filter_bad_packets {
-                    // Bad OSC packet routed
-                    bcst_tx.send(buf.to_vec()).unwrap();
-                    if let Some(ref dbgs) = debug_sender {
-                        routedbg::send_indbg_packet(
-                            dbgs,
-                            &buf,
-                            None,
-                            address.to_string(),
-                            routedbg::IncomingDebugMode::ALLOWED,
-                        );
-                    }
-                } else {
-                    if let Some(ref dbgs) = debug_sender {
-                        routedbg::send_indbg_packet(
-                            dbgs,
-                            &buf,
-                            None,
-                            address.to_string(),
-                            routedbg::IncomingDebugMode::DROPPED,
-                        );
-                    }
-                }
+/*
+    Run a packet through the packet filter.
+    Returns the packets to forward (empty if dropped). Packets the filter didn't change are shared, not copied.
+*/
+pub fn filter_packet(pf: &PacketFilter, buf: &SharedOscBuf) -> Vec<SharedOscBuf> {
+    if !pf.enabled {
+        return vec![buf.clone()];
+    }
+
+    let pkt = match buf.pkt {
+        Some(ref pkt) => pkt,
+        None => {
+            // Packet was bad should it still be sent?
+            if !pf.filter_bad_packets {
+                // Bad OSC packet routed
+                return vec![buf.clone()];
             }
+            return vec![];
         }
-    } else if pf.bl_enabled {
-        // Blacklist
-        match rosc::decoder::decode_udp(&buf) {
-            Ok(ref pkt) => {
-                if let OscPacket::Message(msg) = &pkt.1 {
-                    if !pf_bl.contains(&msg.addr) {
-                        let encoded_packet_buf = encoder::encode(&pkt.1).unwrap();
-                        bcst_tx.send(encoded_packet_buf).unwrap();
-
-                        if let Some(ref dbgs) = debug_sender {
-                            routedbg::send_indbg_packet(
-                                dbgs,
-                                &buf,
-                                Some(pkt.1.clone()),
-                                address.to_string(),
-                                routedbg::IncomingDebugMode::ALLOWED,
-                            );
-                        }
-                    } else {
-                        if let Some(ref dbgs) = debug_sender {
-                            routedbg::send_indbg_packet(
-                                dbgs,
-                                &buf,
-                                Some(pkt.1.clone()),
-                                address.to_string(),
-                                routedbg::IncomingDebugMode::DROPPED,
-                            );
-                        }
-                    }
-                }
+    };
+
+    let forward = match filter_osc_packet(pf, pkt) {
+        Some(allowed) => match pf.bundle_mode {
+            BundleMode::Rebuild => vec![allowed],
+            BundleMode::Flatten => {
+                let mut messages = Vec::new();
+                flatten_osc_packet(allowed, &mut messages);
+                messages
             }
-            Err(_e) => {
-                // Packet was bad should it still be sent?
-                if !pf.filter_bad_packets {
-                    // Bad OSC packet routed
-                    bcst_tx.send(buf.to_vec()).unwrap();
-                    if let Some(ref dbgs) = debug_sender {
-                        routedbg::send_indbg_packet(
-                            dbgs,
-                            &buf,
-                            None,
-                            address.to_string(),
-                            routedbg::IncomingDebugMode::ALLOWED,
-                        );
-                    }
-                } else {
-                    if let Some(ref dbgs) = debug_sender {
-                        routedbg::send_indbg_packet(
-                            dbgs,
-                            &buf,
-                            None,
-                            address.to_string(),
-                            routedbg::IncomingDebugMode::DROPPED,
-                        );
-                    }
-                }
+        },
+        None => vec![],
+    };
+
+    forward
+        .into_iter()
+        .filter_map(|p| {
+            if p == *pkt {
+                Some(buf.clone())
+            } else {
+                OscBuf::encode(p).map(Arc::new)
             }
-        }
+        })
+        .collect()
+}
+
+fn address_allowed(pf: &PacketFilter, addr: &str) -> bool {
+    if pf.wl_enabled {
+        // Whitelist
+        pf.address_wl.iter().any(|a| a.0 == addr)
+    } else if pf.bl_enabled {
+        // Blacklist
+        !pf.address_bl.iter().any(|a| a.0 == addr)
     } else {
         // No mode selected
+        true
+    }
+}
 
-        if pf.filter_bad_packets {
-            // If filter bad packets enabled check if bad packet
-            if let Ok(pkt) = rosc::decoder::decode_udp(&buf) {
-
-                let encoded_packet_buf = encoder::encode(&pkt.1).unwrap();
-                bcst_tx.send(encoded_packet_buf).unwrap();
-                if let Some(ref dbgs) = debug_sender {
-                    routedbg::send_indbg_packet(
-                        dbgs,
-                        &buf,
-                        Some(pkt.1),
-                        address.to_string(),
-                        routedbg::IncomingDebugMode::ALLOWED,
-                    );
-                }
+// Apply the address rules to every message in a packet. Bundles are rebuilt with only the surviving messages.
+fn filter_osc_packet(pf: &PacketFilter, pkt: &OscPacket) -> Option<OscPacket> {
+    match pkt {
+        OscPacket::Message(msg) => {
+            if address_allowed(pf, &msg.addr) {
+                Some(pkt.clone())
             } else {
-                /*println!("[*] Filtered bad packet");*/
-                if let Some(ref dbgs) = debug_sender {
-                    routedbg::send_indbg_packet(
-                        dbgs,
-                        &buf,
-                        None,
-                        address.to_string(),
-                        routedbg::IncomingDebugMode::DROPPED,
-                    );
-                }
+                None
             }
-        } else {
-            // If filter bad packets not enabled then send!
-            bcst_tx.send(buf.to_vec()).unwrap();
-            if let Some(ref dbgs) = debug_sender {
-                // Try to get parsed packet
-                if let Ok(pkt) = rosc::decoder::decode_udp(&buf) {
-                    routedbg::send_indbg_packet(
-                        dbgs,
-                        &buf,
-                        Some(pkt.1),
-                        address.to_string(),
-                        routedbg::IncomingDebugMode::ALLOWED,
-                    );
-                } else {
-                    // Still ALLOWED because filter bad packets is disabled
-                    routedbg::send_indbg_packet(
-                        dbgs,
-                        &buf,
-                        None,
-                        address.to_string(),
-                        routedbg::IncomingDebugMode::ALLOWED,
-                    );
-                }
+        }
+        OscPacket::Bundle(bundle) => {
+            let content: Vec<OscPacket> = bundle
+                .content
+                .iter()
+                .filter_map(|p| filter_osc_packet(pf, p))
+                .collect();
+
+            // Drop bundles left empty
+            if content.is_empty() {
+                None
+            } else {
+                Some(OscPacket::Bundle(OscBundle {
+                    timetag: bundle.timetag,
+                    content,
+                }))
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Pull every message out of (nested) bundles. Bundle timetags are lost.
+fn flatten_osc_packet(pkt: OscPacket, messages: &mut Vec<OscPacket>) {
+    match pkt {
+        OscPacket::Message(_) => messages.push(pkt),
+        OscPacket::Bundle(bundle) => {
+            for p in bundle.content {
+                flatten_osc_packet(p, messages);
+            }
+        }
+    }
+}
+
+// Filter a received packet, count it and send it to debug. Returns the packets to broadcast to the routes.
+#[inline]
+pub fn packet_filter(
+    pf: &PacketFilter,
+    buf: &SharedOscBuf,
+    address: &String,
+    listener: &str,
+    debug_sender: &Option<Sender<routedbg::DebugPacket>>,
+    stats: &mut ListenerStats,
+) -> Vec<SharedOscBuf> {
+    let forward_bufs = filter_packet(pf, buf);
+    stats.count(!forward_bufs.is_empty(), buf.pkt.is_none());
+
+    let mode = if forward_bufs.is_empty() {
+        routedbg::IncomingDebugMode::DROPPED
+    } else {
+        routedbg::IncomingDebugMode::ALLOWED
+    };
+
+    if let Some(ref dbgs) = debug_sender {
+        // Still ALLOWED if the packet is bad because PF is disabled
+        routedbg::send_indbg_packet(dbgs, &buf.bytes, buf.pkt.clone(), address.to_string(), listener.to_string(), mode);
+    }
+
+    forward_bufs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::{encoder, OscMessage, OscTime, OscType};
+
+    fn msg(addr: &str) -> OscPacket {
+        OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Float(1.0)],
+        })
+    }
+
+    fn bundle(content: Vec<OscPacket>) -> OscPacket {
+        OscPacket::Bundle(OscBundle {
+            timetag: OscTime::from((1, 0)),
+            content,
+        })
+    }
+
+    // (/a, (/b, (/c, /d)))
+    fn nested_bundle() -> SharedOscBuf {
+        let bytes = encoder::encode(&bundle(vec![
+            msg("/a"),
+            bundle(vec![msg("/b"), bundle(vec![msg("/c"), msg("/d")])]),
+        ]))
+        .unwrap();
+        Arc::new(OscBuf::decode(&bytes))
+    }
+
+    fn whitelist(addrs: &[&str], bundle_mode: BundleMode) -> PacketFilter {
+        PacketFilter {
+            enabled: true,
+            wl_enabled: true,
+            address_wl: addrs.iter().map(|a| (a.to_string(), false)).collect(),
+            bundle_mode,
+            ..Default::default()
+        }
+    }
+
+    fn decode(buf: &[u8]) -> OscPacket {
+        rosc::decoder::decode_udp(buf).unwrap().1
+    }
+
+    #[test]
+    fn whitelist_rebuilds_nested_bundle() {
+        let pf = whitelist(&["/a", "/d"], BundleMode::Rebuild);
+        let forward = filter_packet(&pf, &nested_bundle());
+
+        assert_eq!(forward.len(), 1);
+        assert_eq!(
+            decode(&forward[0].bytes),
+            bundle(vec![msg("/a"), bundle(vec![bundle(vec![msg("/d")])])])
+        );
+    }
+
+    #[test]
+    fn whitelist_flattens_nested_bundle() {
+        let pf = whitelist(&["/b", "/c", "/d"], BundleMode::Flatten);
+        let forward = filter_packet(&pf, &nested_bundle());
+
+        let forwarded: Vec<OscPacket> = forward.iter().map(|b| decode(&b.bytes)).collect();
+        assert_eq!(forwarded, vec![msg("/b"), msg("/c"), msg("/d")]);
+    }
+
+    #[test]
+    fn blacklist_removes_empty_nested_bundles() {
+        let pf = PacketFilter {
+            enabled: true,
+            bl_enabled: true,
+            address_bl: vec![("/c".to_string(), false), ("/d".to_string(), false)],
+            ..Default::default()
+        };
+        let forward = filter_packet(&pf, &nested_bundle());
+
+        assert_eq!(forward.len(), 1);
+        assert_eq!(
+            decode(&forward[0].bytes),
+            bundle(vec![msg("/a"), bundle(vec![msg("/b")])])
+        );
+    }
+
+    #[test]
+    fn bundle_without_allowed_messages_is_dropped() {
+        let pf = whitelist(&["/x"], BundleMode::Rebuild);
+        let forward = filter_packet(&pf, &nested_bundle());
+
+        assert!(forward.is_empty());
+    }
+
+    #[test]
+    fn no_mode_forwards_whole_bundle() {
+        let pf = PacketFilter {
+            enabled: true,
+            ..Default::default()
+        };
+        let buf = nested_bundle();
+        let forward = filter_packet(&pf, &buf);
+
+        // Unchanged packets are shared with the listener's buffer
+        assert_eq!(forward.len(), 1);
+        assert!(Arc::ptr_eq(&forward[0], &buf));
+    }
+
+    #[test]
+    fn allowed_bad_packet_keeps_received_length() {
+        let pf = PacketFilter {
+            enabled: true,
+            ..Default::default()
+        };
+        let buf = Arc::new(OscBuf::decode(b"/bad\0"));
+        let forward = filter_packet(&pf, &buf);
+
+        assert!(buf.pkt.is_none());
+        assert_eq!(forward.len(), 1);
+        assert_eq!(forward[0].bytes, b"/bad\0");
+
+        let pf = PacketFilter {
+            filter_bad_packets: true,
+            ..pf
+        };
+        assert!(filter_packet(&pf, &buf).is_empty());
+    }
+}
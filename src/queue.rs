@@ -0,0 +1,282 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::routing::RouterPacket;
+
+// Queue fill (percent of its size) that raises the route's warning and the fill that clears it
+const HIGH_WATER_PERCENT: usize = 75;
+const LOW_WATER_PERCENT: usize = 25;
+
+// What a route's queue does with new packets when the app can't keep up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum QueuePolicy {
+    // Drop the oldest queued packet to make room
+    #[default]
+    DropOldest,
+    // Drop the new packet
+    DropNewest,
+    // Replace the queued message with the same address. Other packets drop the oldest when full.
+    Coalesce,
+}
+
+// Counters of a route queue
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueStats {
+    pub dropped: u64,
+    pub coalesced: u64,
+}
+
+struct QueueState {
+    packets: VecDeque<RouterPacket>,
+    // Sequence number of packets[0]
+    head: u64,
+    // Address -> sequence number of its queued message (Coalesce only)
+    addresses: HashMap<String, u64>,
+    stats: QueueStats,
+    closed: bool,
+}
+
+impl QueueState {
+    fn pop_front(&mut self) -> Option<RouterPacket> {
+        let p = self.packets.pop_front()?;
+        if let Some(msg) = p.buf.message() {
+            if self.addresses.get(&msg.addr) == Some(&self.head) {
+                self.addresses.remove(&msg.addr);
+            }
+        }
+        self.head += 1;
+        Some(p)
+    }
+}
+
+/*
+    Packet queue between the listeners and one app route.
+    Listeners never wait on a route. When the queue is full its policy decides which packet is lost.
+*/
+pub struct RouteQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    size: usize,
+    policy: QueuePolicy,
+}
+
+impl RouteQueue {
+    pub fn new(size: usize, policy: QueuePolicy) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                packets: VecDeque::new(),
+                head: 0,
+                addresses: HashMap::new(),
+                stats: QueueStats::default(),
+                closed: false,
+            }),
+            notify: Notify::new(),
+            size: size.max(1),
+            policy,
+        }
+    }
+
+    pub fn push(&self, p: RouterPacket) {
+        let mut state = self.state.lock().unwrap();
+
+        if self.policy == QueuePolicy::Coalesce {
+            if let Some(msg) = p.buf.message() {
+                if let Some(&seq) = state.addresses.get(&msg.addr) {
+                    let i = (seq - state.head) as usize;
+                    state.packets[i] = p;
+                    state.stats.coalesced += 1;
+                    return;
+                }
+            }
+        }
+
+        if state.packets.len() >= self.size {
+            state.stats.dropped += 1;
+            match self.policy {
+                QueuePolicy::DropNewest => return,
+                QueuePolicy::DropOldest | QueuePolicy::Coalesce => {
+                    state.pop_front();
+                }
+            }
+        }
+
+        if self.policy == QueuePolicy::Coalesce {
+            if let Some(msg) = p.buf.message() {
+                let seq = state.head + state.packets.len() as u64;
+                state.addresses.insert(msg.addr.clone(), seq);
+            }
+        }
+        state.packets.push_back(p);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    // Wait for the next packet. None once the queue is closed and empty.
+    pub async fn recv(&self) -> Option<RouterPacket> {
+        loop {
+            // Created before checking so a push in between still wakes us
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(p) = state.pop_front() {
+                    return Some(p);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().packets.len()
+    }
+
+    pub fn high_water(&self) -> usize {
+        (self.size * HIGH_WATER_PERCENT / 100).max(1)
+    }
+
+    pub fn low_water(&self) -> usize {
+        self.size * LOW_WATER_PERCENT / 100
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+type QueueList = Vec<(i64, Arc<RouteQueue>)>;
+
+// Queues of the running routes. Listeners push every packet to each of them.
+#[derive(Clone, Default)]
+pub struct RouteQueues(Arc<RwLock<QueueList>>);
+
+impl RouteQueues {
+    // Replaces (and closes) the route's previous queue
+    pub fn add(&self, id: i64, queue: Arc<RouteQueue>) {
+        let mut queues = self.0.write().unwrap();
+        for (_, q) in queues.iter().filter(|q| q.0 == id) {
+            q.close();
+        }
+        queues.retain(|q| q.0 != id);
+        queues.push((id, queue));
+    }
+
+    pub fn remove(&self, id: i64) {
+        let mut queues = self.0.write().unwrap();
+        for (_, q) in queues.iter().filter(|q| q.0 == id) {
+            q.close();
+        }
+        queues.retain(|q| q.0 != id);
+    }
+
+    pub fn close_all(&self) {
+        for (_, q) in self.0.write().unwrap().drain(..) {
+            q.close();
+        }
+    }
+
+    pub fn push(&self, p: RouterPacket) {
+        for (_, q) in self.0.read().unwrap().iter() {
+            q.push(p.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::OscBuf;
+    use rosc::{encoder, OscMessage, OscPacket, OscType};
+    use std::time::Instant;
+
+    fn packet(addr: &str, value: i32) -> RouterPacket {
+        let bytes = encoder::encode(&OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Int(value)],
+        }))
+        .unwrap();
+        RouterPacket {
+            buf: Arc::new(OscBuf::decode(&bytes)),
+            received: Instant::now(),
+        }
+    }
+
+    // (address, value) of every queued packet
+    fn drain(queue: &RouteQueue) -> Vec<(String, i32)> {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        queue.close();
+        let mut packets = Vec::new();
+        while let Some(p) = rt.block_on(queue.recv()) {
+            let msg = p.buf.message().unwrap();
+            match msg.args[0] {
+                OscType::Int(v) => packets.push((msg.addr.clone(), v)),
+                _ => unreachable!(),
+            }
+        }
+        packets
+    }
+
+    fn fill(queue: &RouteQueue) {
+        queue.push(packet("/a", 1));
+        queue.push(packet("/b", 2));
+        queue.push(packet("/a", 3));
+        queue.push(packet("/c", 4));
+    }
+
+    #[test]
+    fn drop_oldest_keeps_newest_packets() {
+        let queue = RouteQueue::new(3, QueuePolicy::DropOldest);
+        fill(&queue);
+
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(
+            drain(&queue),
+            vec![("/b".to_string(), 2), ("/a".to_string(), 3), ("/c".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn drop_newest_keeps_queued_packets() {
+        let queue = RouteQueue::new(3, QueuePolicy::DropNewest);
+        fill(&queue);
+
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(
+            drain(&queue),
+            vec![("/a".to_string(), 1), ("/b".to_string(), 2), ("/a".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn coalesce_replaces_queued_address() {
+        let queue = RouteQueue::new(2, QueuePolicy::Coalesce);
+        fill(&queue);
+
+        // /a was updated in place, /c pushed out /a when the queue was full
+        assert_eq!(queue.stats(), QueueStats { dropped: 1, coalesced: 1 });
+        assert_eq!(drain(&queue), vec![("/b".to_string(), 2), ("/c".to_string(), 4)]);
+
+        // Addresses that left the queue are queued again
+        let queue = RouteQueue::new(2, QueuePolicy::Coalesce);
+        queue.push(packet("/a", 1));
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(queue.recv());
+        queue.push(packet("/a", 2));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(drain(&queue), vec![("/a".to_string(), 2)]);
+    }
+}
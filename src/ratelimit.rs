@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::packet::SharedOscBuf;
+use crate::vorutils::check_valid_u64;
+
+// How often a route reports its rate limit counters
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/*
+    Per-route rate limit config.
+    Empty or 0 disables an option.
+*/
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RateLimit {
+    // Max messages sent to the app per second. Messages over the limit are dropped.
+    pub max_per_second: String,
+    // Coalescing window in milliseconds. Only the latest value for each address is sent per window.
+    pub coalesce_ms: String,
+}
+
+impl RateLimit {
+    pub fn is_valid(&self) -> bool {
+        check_valid_u64(&self.max_per_second) && check_valid_u64(&self.coalesce_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimitStats {
+    pub coalesced: u64,
+    pub dropped: u64,
+}
+
+impl fmt::Display for RateLimitStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Coalesced: {} Dropped: {}", self.coalesced, self.dropped)
+    }
+}
+
+pub struct RouteLimiter {
+    max_per_second: u64,
+    window_start: Instant,
+    window_count: u64,
+    coalesce_window: Option<Duration>,
+    next_flush: Instant,
+    // Latest buffer for each address in the current coalescing window (in arrival order)
+    pending: Vec<SharedOscBuf>,
+    pending_index: HashMap<String, usize>,
+    stats: RateLimitStats,
+    reported_stats: RateLimitStats,
+    last_report: Instant,
+}
+
+impl RouteLimiter {
+    pub fn new(config: &RateLimit) -> Self {
+        let now = Instant::now();
+        let coalesce_ms = config.coalesce_ms.parse::<u64>().unwrap_or(0);
+        let coalesce_window = if coalesce_ms > 0 {
+            Some(Duration::from_millis(coalesce_ms))
+        } else {
+            None
+        };
+
+        Self {
+            max_per_second: config.max_per_second.parse::<u64>().unwrap_or(0),
+            window_start: now,
+            window_count: 0,
+            coalesce_window,
+            next_flush: now + coalesce_window.unwrap_or_default(),
+            pending: Vec::new(),
+            pending_index: HashMap::new(),
+            stats: RateLimitStats::default(),
+            reported_stats: RateLimitStats::default(),
+            last_report: now,
+        }
+    }
+
+    // Returns the buffer if it should be sent now
+    pub fn limit(&mut self, buf: SharedOscBuf) -> Option<SharedOscBuf> {
+        if self.coalesce_window.is_some() {
+            // Bundles and bad packets are not coalesced
+            if let Some(msg) = buf.message() {
+                match self.pending_index.get(&msg.addr) {
+                    Some(&i) => {
+                        self.pending[i] = buf;
+                        self.stats.coalesced += 1;
+                    }
+                    None => {
+                        self.pending_index.insert(msg.addr.clone(), self.pending.len());
+                        self.pending.push(buf);
+                    }
+                }
+                return None;
+            }
+        }
+
+        if self.take_rate() {
+            Some(buf)
+        } else {
+            None
+        }
+    }
+
+    // Coalesced buffers to send if the window ended
+    pub fn flush(&mut self) -> Vec<SharedOscBuf> {
+        let window = match self.coalesce_window {
+            Some(w) => w,
+            None => return vec![],
+        };
+
+        let now = Instant::now();
+        if now < self.next_flush {
+            return vec![];
+        }
+        self.next_flush = now + window;
+
+        self.pending_index.clear();
+        let pending = std::mem::take(&mut self.pending);
+        pending.into_iter().filter(|_| self.take_rate()).collect()
+    }
+
+    // Counters to report if they changed since the last report
+    pub fn take_stats_update(&mut self) -> Option<RateLimitStats> {
+        if self.stats == self.reported_stats || self.last_report.elapsed() < STATS_REPORT_INTERVAL {
+            return None;
+        }
+        self.reported_stats = self.stats;
+        self.last_report = Instant::now();
+        Some(self.stats)
+    }
+
+    // When the route needs to flush or report next
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let flush = if self.pending.is_empty() {
+            None
+        } else {
+            Some(self.next_flush)
+        };
+        let report = if self.stats == self.reported_stats {
+            None
+        } else {
+            Some(self.last_report + STATS_REPORT_INTERVAL)
+        };
+
+        match (flush, report) {
+            (Some(f), Some(r)) => Some(f.min(r)),
+            (f, r) => f.or(r),
+        }
+    }
+
+    fn take_rate(&mut self) -> bool {
+        if self.max_per_second == 0 {
+            return true;
+        }
+
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.window_count = 0;
+        }
+
+        if self.window_count < self.max_per_second {
+            self.window_count += 1;
+            true
+        } else {
+            self.stats.dropped += 1;
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::OscBuf;
+    use rosc::{encoder, OscMessage, OscPacket, OscType};
+    use std::sync::Arc;
+
+    fn buf(addr: &str, value: i32) -> SharedOscBuf {
+        let bytes = encoder::encode(&OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Int(value)],
+        }))
+        .unwrap();
+        Arc::new(OscBuf::decode(&bytes))
+    }
+
+    // (address, value) of each buffer
+    fn values(bufs: &[SharedOscBuf]) -> Vec<(String, i32)> {
+        bufs.iter()
+            .map(|b| {
+                let msg = b.message().unwrap();
+                match msg.args[0] {
+                    OscType::Int(v) => (msg.addr.clone(), v),
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    fn limiter(max_per_second: &str, coalesce_ms: &str) -> RouteLimiter {
+        RouteLimiter::new(&RateLimit {
+            max_per_second: max_per_second.to_string(),
+            coalesce_ms: coalesce_ms.to_string(),
+        })
+    }
+
+    #[test]
+    fn caps_messages_per_window() {
+        let mut limiter = limiter("3", "");
+        let sent: Vec<SharedOscBuf> = (0..5).filter_map(|i| limiter.limit(buf("/a", i))).collect();
+        assert_eq!(values(&sent), vec![("/a".to_string(), 0), ("/a".to_string(), 1), ("/a".to_string(), 2)]);
+        assert_eq!(limiter.stats, RateLimitStats { coalesced: 0, dropped: 2 });
+
+        // The next window starts over
+        limiter.window_start -= Duration::from_secs(1);
+        assert!(limiter.limit(buf("/a", 5)).is_some());
+    }
+
+    #[test]
+    fn coalesces_latest_value_per_window() {
+        let mut limiter = limiter("", "50");
+        for (addr, value) in [("/a", 1), ("/b", 2), ("/a", 3)] {
+            assert!(limiter.limit(buf(addr, value)).is_none());
+        }
+        assert_eq!(limiter.stats.coalesced, 1);
+
+        // Nothing is sent before the window ends
+        assert!(limiter.flush().is_empty());
+        assert_eq!(limiter.next_wakeup(), Some(limiter.next_flush));
+
+        // Latest value for each address, in arrival order
+        limiter.next_flush = Instant::now();
+        assert_eq!(values(&limiter.flush()), vec![("/a".to_string(), 3), ("/b".to_string(), 2)]);
+        assert!(limiter.flush().is_empty());
+    }
+
+    #[test]
+    fn flushes_coalesced_values_within_rate() {
+        let mut limiter = limiter("1", "50");
+        assert!(limiter.limit(buf("/a", 1)).is_none());
+        assert!(limiter.limit(buf("/b", 2)).is_none());
+
+        limiter.next_flush = Instant::now();
+        assert_eq!(values(&limiter.flush()), vec![("/a".to_string(), 1)]);
+        assert_eq!(limiter.stats, RateLimitStats { coalesced: 0, dropped: 1 });
+        assert_eq!(limiter.next_wakeup(), Some(limiter.last_report + STATS_REPORT_INTERVAL));
+    }
+}
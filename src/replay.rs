@@ -0,0 +1,396 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::capture::{read_capture, CapturedPacket};
+use crate::config::{RouterConfig, VORConfig};
+use crate::packet::OscBuf;
+use crate::pf::{filter_packet, PacketFilter};
+use crate::transport::Transport;
+use crate::vorutils::{host_port, resolve_target, unspecified_for};
+
+// Longest sleep between stop flag checks
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayOptions {
+    // Playback speed multiplier, 0 sends as fast as possible
+    pub speed: f64,
+    // Start over at the end until stopped
+    pub looped: bool,
+    // Time range from the first packet of the capture
+    pub from: Option<Duration>,
+    pub to: Option<Duration>,
+    // Only replay messages with these OSC addresses (All if empty)
+    pub addresses: Vec<String>,
+}
+
+impl ReplayOptions {
+    // Rejects negative, NaN and infinite speeds and times (Times in seconds from the first packet)
+    pub fn new(speed: f64, looped: bool, from: Option<f64>, to: Option<f64>, addresses: Vec<String>) -> Result<Self, String> {
+        if !speed.is_finite() || speed < 0. {
+            return Err(format!("Invalid replay speed: {}", speed));
+        }
+        let seconds = |secs: Option<f64>| match secs {
+            Some(s) => Duration::try_from_secs_f64(s)
+                .map(Some)
+                .map_err(|_| format!("Invalid replay time: {}", s)),
+            None => Ok(None),
+        };
+        Ok(Self {
+            speed,
+            looped,
+            from: seconds(from)?,
+            to: seconds(to)?,
+            addresses,
+        })
+    }
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            looped: false,
+            from: None,
+            to: None,
+            addresses: Vec::new(),
+        }
+    }
+}
+
+// Where replayed packets are sent
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayTarget {
+    // The router listener each packet was captured on. Packets without a listener name and unknown listeners go to the first (VRChat) listener.
+    Listeners(Vec<(String, SocketAddr)>),
+    // Straight to an app, bypassing the router
+    App(SocketAddr),
+}
+
+impl ReplayTarget {
+    // Listeners bound to the unspecified address are sent to on loopback
+    pub fn listeners(config: &RouterConfig) -> Result<Self, String> {
+        let mut targets = Vec::new();
+        for (name, target) in config.listener_targets() {
+            let addr = resolve_target(&target)?;
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ip => ip,
+            };
+            targets.push((name, SocketAddr::new(ip, addr.port())));
+        }
+        Ok(ReplayTarget::Listeners(targets))
+    }
+
+    pub fn app(app: &VORConfig) -> Result<Self, String> {
+        if app.transport != Transport::Udp {
+            return Err(format!("{}: Only UDP apps can be replayed to", app.app_name));
+        }
+        Ok(ReplayTarget::App(resolve_target(&host_port(&app.app_host, &app.app_port))?))
+    }
+
+    fn destination(&self, packet: &CapturedPacket) -> SocketAddr {
+        match self {
+            ReplayTarget::Listeners(targets) => targets
+                .iter()
+                .find(|t| packet.listener.as_deref() == Some(t.0.as_str()))
+                .unwrap_or(&targets[0])
+                .1,
+            ReplayTarget::App(addr) => *addr,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ReplayTarget::Listeners(_) => "the router listeners".to_string(),
+            ReplayTarget::App(addr) => addr.to_string(),
+        }
+    }
+}
+
+// A packet ready to send and its time from the start of the replayed range
+struct ReplayPacket {
+    time: Duration,
+    to: SocketAddr,
+    bytes: Vec<u8>,
+}
+
+/*
+    Packets in the time range whose messages pass the address filter.
+    Bundles keep only the matching messages. Packets that aren't valid OSC are dropped when filtering by address.
+*/
+fn select_packets(packets: &[CapturedPacket], target: &ReplayTarget, opts: &ReplayOptions) -> Vec<ReplayPacket> {
+    let pf = PacketFilter {
+        enabled: !opts.addresses.is_empty(),
+        filter_bad_packets: true,
+        wl_enabled: true,
+        address_wl: opts.addresses.iter().map(|a| (a.clone(), false)).collect(),
+        ..Default::default()
+    };
+    let from = opts.from.unwrap_or_default();
+
+    let mut selected = Vec::new();
+    for p in packets {
+        if p.time < from || opts.to.is_some_and(|to| p.time > to) {
+            continue;
+        }
+        let buf = Arc::new(OscBuf::decode(&p.bytes));
+        for forward in filter_packet(&pf, &buf) {
+            selected.push(ReplayPacket {
+                time: p.time - from,
+                to: target.destination(p),
+                bytes: forward.bytes.clone(),
+            });
+        }
+    }
+    selected
+}
+
+// Sleep until the deadline. Returns false if stopped.
+fn wait_until(deadline: Instant, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(STOP_CHECK_INTERVAL));
+    }
+}
+
+// Send the capture's packets with their original timing. Returns the number of packets sent.
+pub fn replay(packets: &[CapturedPacket], target: &ReplayTarget, opts: &ReplayOptions, stop: &AtomicBool) -> Result<u64, String> {
+    let selected = select_packets(packets, target, opts);
+    if selected.is_empty() {
+        return Ok(0);
+    }
+
+    // One socket per address family
+    let (mut v4, mut v6) = (None, None);
+    for p in &selected {
+        let sock = if p.to.is_ipv4() { &mut v4 } else { &mut v6 };
+        if sock.is_none() {
+            *sock = Some(UdpSocket::bind(unspecified_for(&p.to)).map_err(|e| format!("Failed to bind replay socket: {}", e))?);
+        }
+    }
+
+    let mut sent = 0;
+    loop {
+        let start = Instant::now();
+        for p in &selected {
+            if opts.speed > 0. {
+                // Slow enough speeds put later packets out of reach, the replay ends there
+                let deadline = Duration::try_from_secs_f64(p.time.as_secs_f64() / opts.speed)
+                    .ok()
+                    .and_then(|d| start.checked_add(d));
+                match deadline {
+                    Some(deadline) if wait_until(deadline, stop) => {}
+                    _ => return Ok(sent),
+                }
+            } else if stop.load(Ordering::Relaxed) {
+                return Ok(sent);
+            }
+
+            let sock = if p.to.is_ipv4() { &v4 } else { &v6 };
+            if let Some(sock) = sock {
+                // A listener or app that isn't running doesn't end the replay
+                if sock.send_to(&p.bytes, p.to).is_ok() {
+                    sent += 1;
+                }
+            }
+        }
+        if !opts.looped {
+            return Ok(sent);
+        }
+    }
+}
+
+// A replay running on its own thread
+pub struct ReplayHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<Result<u64, String>>>,
+}
+
+impl ReplayHandle {
+    pub fn start(path: String, target: ReplayTarget, opts: ReplayOptions) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let packets = read_capture(&path)?;
+            replay(&packets, &target, &opts, &thread_stop)
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    // Packets sent once the replay has ended
+    pub fn finished(&mut self) -> Option<Result<u64, String>> {
+        if self.thread.as_ref().is_some_and(|t| t.is_finished()) {
+            return self.join();
+        }
+        None
+    }
+
+    pub fn stop(&mut self) -> Option<Result<u64, String>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    fn join(&mut self) -> Option<Result<u64, String>> {
+        let t = self.thread.take()?;
+        Some(t.join().unwrap_or_else(|_| Err("Replay thread panicked".to_string())))
+    }
+}
+
+// Replay a capture file from the command line until it ends or Ctrl+C
+pub fn replay_main(path: String, route: Option<String>, opts: ReplayOptions, config: RouterConfig, configs: Vec<VORConfig>) {
+    let target = match route {
+        Some(name) => match configs.iter().find(|c| c.app_name == name) {
+            Some(app) => ReplayTarget::app(app),
+            None => Err(format!("No app named {}", name)),
+        },
+        None => ReplayTarget::listeners(&config),
+    };
+    let target = match target {
+        Ok(t) => t,
+        Err(e) => {
+            println!("[-] {}", e);
+            return;
+        }
+    };
+
+    let packets = match read_capture(&path) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("[-] {}", e);
+            return;
+        }
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed)) {
+        println!("[-] Failed to set signal handler: {}", e);
+        return;
+    }
+
+    println!(
+        "[*] Replaying {} packet(s) from {} to {}",
+        packets.len(),
+        path,
+        target.describe()
+    );
+    match replay(&packets, &target, &opts, &stop) {
+        Ok(sent) => println!("[*] Replayed {} packet(s)", sent),
+        Err(e) => println!("[-] {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscTime};
+
+    fn bundle(content: Vec<OscPacket>) -> OscPacket {
+        OscPacket::Bundle(OscBundle {
+            timetag: OscTime { seconds: 0, fractional: 1 },
+            content,
+        })
+    }
+
+    fn message(addr: &str) -> OscPacket {
+        OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args: vec![],
+        })
+    }
+
+    fn captured(ms: u64, listener: &str, pkt: OscPacket) -> CapturedPacket {
+        CapturedPacket {
+            time: Duration::from_millis(ms),
+            listener: Some(listener.to_string()),
+            from: "127.0.0.1:50000".parse().unwrap(),
+            allowed: Some(true),
+            bytes: encoder::encode(&pkt).unwrap(),
+        }
+    }
+
+    #[test]
+    fn selects_range_addresses_and_listeners() {
+        let target = ReplayTarget::Listeners(vec![
+            ("VRChat".to_string(), "127.0.0.1:9001".parse().unwrap()),
+            ("Tracker".to_string(), "127.0.0.1:9100".parse().unwrap()),
+        ]);
+        let packets = vec![
+            captured(0, "VRChat", message("/a")),
+            captured(100, "Tracker", bundle(vec![message("/a"), message("/b")])),
+            captured(200, "Gone", message("/a")),
+            captured(300, "VRChat", message("/a")),
+        ];
+        let opts = ReplayOptions {
+            from: Some(Duration::from_millis(100)),
+            to: Some(Duration::from_millis(200)),
+            addresses: vec!["/a".to_string()],
+            ..Default::default()
+        };
+
+        let selected = select_packets(&packets, &target, &opts);
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].time, Duration::ZERO);
+        assert_eq!(selected[0].to.port(), 9100);
+        // The bundle keeps only /a
+        assert_eq!(selected[0].bytes, encoder::encode(&bundle(vec![message("/a")])).unwrap());
+        // Unknown listeners go to VRChat's
+        assert_eq!(selected[1].time, Duration::from_millis(100));
+        assert_eq!(selected[1].to.port(), 9001);
+    }
+
+    #[test]
+    fn replays_to_app_socket() {
+        let app = UdpSocket::bind("127.0.0.1:0").unwrap();
+        app.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let target = ReplayTarget::App(app.local_addr().unwrap());
+        let packets = vec![captured(0, "VRChat", message("/a")), captured(10, "VRChat", message("/b"))];
+        let opts = ReplayOptions {
+            speed: 0.,
+            ..Default::default()
+        };
+
+        assert_eq!(replay(&packets, &target, &opts, &AtomicBool::new(false)), Ok(2));
+        let mut buf = [0u8; 64];
+        let n = app.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &packets[0].bytes[..]);
+
+        // A stopped replay sends nothing
+        assert_eq!(replay(&packets, &target, &opts, &AtomicBool::new(true)), Ok(0));
+
+        // Packets that would be sent after the end of time end the replay
+        let opts = ReplayOptions {
+            speed: 1e-300,
+            ..Default::default()
+        };
+        assert_eq!(replay(&packets, &target, &opts, &AtomicBool::new(false)), Ok(1));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert!(ReplayOptions::new(f64::NAN, false, None, None, vec![]).is_err());
+        assert!(ReplayOptions::new(-1., false, None, None, vec![]).is_err());
+        assert!(ReplayOptions::new(f64::INFINITY, false, None, None, vec![]).is_err());
+        assert!(ReplayOptions::new(1., false, Some(-1.), None, vec![]).is_err());
+        assert!(ReplayOptions::new(1., false, None, Some(1e300), vec![]).is_err());
+
+        let opts = ReplayOptions::new(0., true, Some(1.5), None, vec![]).unwrap();
+        assert_eq!(opts.speed, 0.);
+        assert_eq!(opts.from, Some(Duration::from_millis(1500)));
+        assert_eq!(opts.to, None);
+    }
+}
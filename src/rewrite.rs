@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use regex::Regex;
+use rosc::OscPacket;
+use serde::{Deserialize, Serialize};
+
+use crate::packet::{OscBuf, SharedOscBuf};
+
+/*
+    Per-route OSC address rewrite table.
+    Rules are checked in this order and the first match wins:
+        1. Exact renames
+        2. Prefix replacements
+        3. Regex rewrites (replacement can use capture groups: $1, ${name})
+*/
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RewriteRules {
+    pub rename: Vec<(String, String)>,
+    pub prefix: Vec<(String, String)>,
+    pub regex: Vec<(String, String)>,
+}
+
+// Rewrite rules prepared for the route task
+pub struct AddressRewriter {
+    rename: HashMap<String, String>,
+    prefix: Vec<(String, String)>,
+    regex: Vec<(Regex, String)>,
+}
+
+impl AddressRewriter {
+    /*
+        Empty matches are rejected, an empty prefix would match every address and hide the rules after it.
+        Replacements must start with / so rewritten addresses are still valid OSC.
+    */
+    pub fn new(rules: &RewriteRules) -> Result<Self, String> {
+        for (matching, replacement) in rules.rename.iter().chain(&rules.prefix).chain(&rules.regex) {
+            if matching.is_empty() {
+                return Err("Empty match".to_string());
+            }
+            if !replacement.starts_with('/') {
+                return Err(format!("{}: Replacement must start with /", matching));
+            }
+        }
+
+        let mut regex = Vec::new();
+        for (pattern, replacement) in &rules.regex {
+            match Regex::new(pattern) {
+                Ok(re) => regex.push((re, replacement.clone())),
+                Err(e) => return Err(format!("{}: {}", pattern, e)),
+            }
+        }
+
+        Ok(Self {
+            rename: rules.rename.iter().cloned().collect(),
+            prefix: rules.prefix.clone(),
+            regex,
+        })
+    }
+
+    // Returns the new address if a rule matched
+    pub fn rewrite_addr(&self, addr: &str) -> Option<String> {
+        if let Some(new_addr) = self.rename.get(addr) {
+            return Some(new_addr.clone());
+        }
+
+        for (prefix, replacement) in &self.prefix {
+            // Only whole address parts match (/avatar/parameters doesn't match /avatar/parametersX)
+            let rest = match addr.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/') => rest,
+                _ => continue,
+            };
+            return Some(format!("{}{}", replacement, rest));
+        }
+
+        for (re, replacement) in &self.regex {
+            if re.is_match(addr) {
+                return Some(re.replace(addr, replacement.as_str()).into_owned());
+            }
+        }
+
+        None
+    }
+
+    // Rewrite every message in the packet. Returns true if any address changed.
+    fn rewrite_packet(&self, pkt: &mut OscPacket) -> bool {
+        match pkt {
+            OscPacket::Message(msg) => match self.rewrite_addr(&msg.addr) {
+                Some(new_addr) => {
+                    msg.addr = new_addr;
+                    true
+                }
+                None => false,
+            },
+            OscPacket::Bundle(bundle) => {
+                let mut changed = false;
+                for p in bundle.content.iter_mut() {
+                    changed |= self.rewrite_packet(p);
+                }
+                changed
+            }
+        }
+    }
+
+    /*
+        Rewrite a route buffer.
+        Returns the rewritten buffer, or None if no address changed and the original should be sent.
+        Buffers that can't be parsed are sent unchanged.
+    */
+    pub fn rewrite_buf(&self, buf: &OscBuf) -> Option<SharedOscBuf> {
+        let mut rewritten = buf.pkt.clone()?;
+        if !self.rewrite_packet(&mut rewritten) {
+            return None;
+        }
+
+        OscBuf::encode(rewritten).map(Arc::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rename: &[(&str, &str)], prefix: &[(&str, &str)], regex: &[(&str, &str)]) -> RewriteRules {
+        let rules = |r: &[(&str, &str)]| r.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        RewriteRules {
+            rename: rules(rename),
+            prefix: rules(prefix),
+            regex: rules(regex),
+        }
+    }
+
+    fn rewriter(rename: &[(&str, &str)], prefix: &[(&str, &str)], regex: &[(&str, &str)]) -> AddressRewriter {
+        AddressRewriter::new(&rules(rename, prefix, regex)).unwrap()
+    }
+
+    #[test]
+    fn renames_exact_address() {
+        let rw = rewriter(&[("/avatar/parameters/Smile", "/face/smile")], &[], &[]);
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/Smile"), Some("/face/smile".to_string()));
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/SmileX"), None);
+    }
+
+    #[test]
+    fn prefix_matches_on_part_boundary() {
+        let rw = rewriter(&[], &[("/avatar/parameters", "/face")], &[]);
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/Smile"), Some("/face/Smile".to_string()));
+        assert_eq!(rw.rewrite_addr("/avatar/parameters"), Some("/face".to_string()));
+
+        // A prefix ending in / matches the addresses under it
+        let rw = rewriter(&[], &[("/avatar/parameters/", "/face/")], &[]);
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/Smile"), Some("/face/Smile".to_string()));
+    }
+
+    #[test]
+    fn prefix_ignores_partial_part() {
+        let rw = rewriter(&[], &[("/avatar/parameters", "/face")], &[]);
+        assert_eq!(rw.rewrite_addr("/avatar/parametersX"), None);
+        assert_eq!(rw.rewrite_addr("/avatar/parametersX/Smile"), None);
+    }
+
+    #[test]
+    fn rejects_empty_match() {
+        assert!(AddressRewriter::new(&rules(&[], &[("", "/face")], &[])).is_err());
+        assert!(AddressRewriter::new(&rules(&[("", "/face")], &[], &[])).is_err());
+    }
+
+    #[test]
+    fn rejects_replacement_without_leading_slash() {
+        assert!(AddressRewriter::new(&rules(&[], &[("/avatar/parameters/", "")], &[])).is_err());
+        assert!(AddressRewriter::new(&rules(&[("/avatar/parameters/Smile", "smile")], &[], &[])).is_err());
+        assert!(AddressRewriter::new(&rules(&[], &[("/avatar/parameters/", "/")], &[])).is_ok());
+    }
+
+    #[test]
+    fn regex_substitutes_capture_groups() {
+        let rw = rewriter(&[], &[], &[(r"^/avatar/parameters/(\w+)_(?P<side>\w+)$", "/${side}/$1")]);
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/Eye_Left"), Some("/Left/Eye".to_string()));
+        assert_eq!(rw.rewrite_addr("/avatar/parameters/Eye"), None);
+    }
+}
@@ -37,6 +37,7 @@ pub struct OSCParsedPacket {
 pub struct VORUIOptions {
     pub show_incoming: bool,
     pub show_outgoing: bool,
+    pub show_returning: bool,
     pub show_dropped: bool,
     pub show_allowed: bool,
     pub search_query: String,
@@ -47,6 +48,7 @@ impl Default for VORUIOptions {
         VORUIOptions {
             show_incoming: true,
             show_outgoing: true,
+            show_returning: true,
             show_dropped: true,
             show_allowed: true,
             search_query: String::new(),
@@ -98,21 +100,37 @@ pub struct IPacket {
     pub osc_packet: Option<OscPacket>,
     pub mode: IncomingDebugMode,
     pub from_address: String,
+    // Name of the listener the packet was received on
+    pub listener: String,
 }
 
 #[derive(Debug)]
 pub struct OPacket {
     pub packet_buffer: Vec<u8>,
     pub osc_packet: Option<rosc::OscPacket>,
+    // Packet before the route's address rewrite (None if not rewritten)
+    pub original_osc_packet: Option<rosc::OscPacket>,
+    pub mode: OutgoingDebugMode,
     pub route: String,
     pub to_address: String,
 }
 
+// App reply being returned to VRChat
+#[derive(Debug)]
+pub struct RPacket {
+    pub packet_buffer: Vec<u8>,
+    pub osc_packet: Option<OscPacket>,
+    pub mode: IncomingDebugMode,
+    pub route: String,
+    pub from_address: String,
+}
+
 // A packet in the form of a debug wrapper
 #[derive(Debug)]
 pub enum DebugPacket {
     INCOMING(IPacket),
     OUTGOING(OPacket),
+    RETURNING(RPacket),
 }
 
 impl DebugPacket {
@@ -132,6 +150,11 @@ impl DebugPacket {
                     return true;
                 }
 
+                // query in listener name
+                if i.listener.to_lowercase().contains(&query) {
+                    return true;
+                }
+
                 // query in mode
                 if format!("{:?}", i.mode).to_lowercase().contains(&query) {
                     return true;
@@ -175,6 +198,13 @@ impl DebugPacket {
                 }
                 return false;
             }
+            Self::RETURNING(r) => {
+                format!("{:?}", self).to_lowercase().contains(&query)
+                    || r.from_address.to_lowercase().contains(&query)
+                    || r.route.to_lowercase().contains(&query)
+                    // query in osc packet address
+                    || matches!(&r.osc_packet, Some(OscPacket::Message(msg)) if msg.addr.to_lowercase().contains(&query))
+            }
         }
     }
 }
@@ -204,11 +234,20 @@ impl IncomingDebugMode {
     }
 }
 
-// Outgoing debug mode (kind of just a filler)
+// Outgoing debug mode
 #[derive(Debug, Clone)]
 pub enum OutgoingDebugMode {
     /* Filter route option? Could just do this on UI side */
     ALL,
+    SENT,
+    // Dropped by the route with the reason
+    DROPPED(String),
+}
+
+impl OutgoingDebugMode {
+    pub fn is_dropped(&self) -> bool {
+        matches!(self, Self::DROPPED(_))
+    }
 }
 
 pub fn send_indbg_packet(
@@ -216,6 +255,7 @@ pub fn send_indbg_packet(
     buf: &[u8],
     osc_packet: Option<OscPacket>,
     from_address: String,
+    listener: String,
     mode: IncomingDebugMode,
 ) {
     let _ = dbgs.send(DebugPacket::INCOMING(IPacket {
@@ -223,6 +263,7 @@ pub fn send_indbg_packet(
         osc_packet,
         mode,
         from_address,
+        listener,
     }));
 }
 
@@ -232,11 +273,32 @@ pub fn send_outdbg_packet(
     to_address: String,
     buf: &[u8],
     osc_packet: Option<OscPacket>,
+    original_osc_packet: Option<OscPacket>,
+    mode: OutgoingDebugMode,
 ) {
     let _ = dbgs.send(DebugPacket::OUTGOING(OPacket {
         packet_buffer: buf.to_vec(),
         osc_packet,
+        original_osc_packet,
+        mode,
         route,
         to_address,
     }));
 }
+
+pub fn send_retdbg_packet(
+    dbgs: &Sender<DebugPacket>,
+    route: String,
+    from_address: String,
+    buf: &[u8],
+    osc_packet: Option<OscPacket>,
+    mode: IncomingDebugMode,
+) {
+    let _ = dbgs.send(DebugPacket::RETURNING(RPacket {
+        packet_buffer: buf.to_vec(),
+        osc_packet,
+        mode,
+        route,
+        from_address,
+    }));
+}
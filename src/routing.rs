@@ -1,229 +1,804 @@
 use rosc::decoder::MTU;
 use rosc;
-use std::net::{UdpSocket, Ipv4Addr};
+use std::collections::HashMap;
+use std::net::{UdpSocket, IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
-use tokio::sync::broadcast::error::RecvError;
-use tokio::sync::broadcast::error::TryRecvError;
-use tokio::sync::broadcast::{self, Receiver as bcst_Receiver, Sender as bcst_Sender};
+use std::time::Instant;
 
-use crate::pf::{packet_filter, PacketFilter};
+use crate::capture::{Capture, CaptureStatus, Recorder};
+use crate::metrics::{Metrics, MetricsServer};
+use crate::oscquery::OscQueryServer;
+use crate::packet::{OscBuf, SharedOscBuf};
+use crate::queue::{RouteQueue, RouteQueues};
+use crate::pf::{filter_packet, packet_filter, PacketFilter};
 use crate::routedbg;
+use crate::ratelimit::{RateLimitStats, RouteLimiter};
+use crate::dedup::RouteDedup;
+use crate::health::{is_refused, RouteHealth};
+use crate::transport::{RouteSink, TcpSink, Transport, UnixSink};
+use crate::websocket::WsSink;
+use crate::rewrite::AddressRewriter;
+use crate::stats::{ListenerStats, RouteStats, StatsReporter};
+use crate::supervisor::RouteSupervisor;
 use crate::{
-    config::{VORAppIdentifier, VORAppStatus, VORConfig},
+    config::{RouterConfig, VORAppIdentifier, VORAppStatus, VORConfig},
     vorerr::app_error,
+    vorutils::{bind_udp, configure_udp_target, host_port, join_multicast, resolve_target, unspecified_for},
 };
 
 pub enum RouterMsg {
     ShutdownAll,
+    AddRoute(VORConfig, i64),
+    RemoveRoute(i64),
+    UpdateRoute(VORConfig, i64),
+    PauseRoute(i64),
+    // Record the listeners' datagrams to a file, replacing a running capture
+    StartCapture(String),
+    StopCapture,
 }
 
-fn route_app(
-    mut rx: bcst_Receiver<Vec<u8>>,
-    router_rx: Receiver<bool>,
-    app_stat_tx_at: Sender<VORAppIdentifier>,
+// Rate limit counters and capture statuses the router reports while running, kept apart from the app statuses
+pub enum RouteEvent {
+    // Route id and its rate limiter's counters
+    RateLimit(i64, RateLimitStats),
+    // Capture file status
+    Capture(CaptureStatus),
+    // Failed send of an app reply to VRChat, None once replies go through again
+    ReturnError(Option<String>),
+}
+
+// What the router loop waits on: messages from the GUI and routes that failed
+// Route configs are sent rarely, boxing them isn't worth it
+#[allow(clippy::large_enum_variant)]
+enum RouterEvent {
+    Msg(RouterMsg),
+    // Route id, route generation and the error it failed on
+    RouteFailed(i64, u64, String),
+}
+
+// Why a route task ended without being stopped
+enum RouteExit {
+    // The config can't work (Invalid rewrite regex), restarting won't help
+    Invalid,
+    // Bind, resolve or repeated send failures. The router restarts the route after a backoff.
+    Failed(String),
+}
+
+// Send the error as the app's status and return it as a failure the route is restarted for
+fn route_failed(app_stat_tx: &Sender<VORAppIdentifier>, ai: i64, err_id: i32, msg: String) -> RouteExit {
+    let _ = app_stat_tx.send(app_error(ai, err_id, msg.clone()));
+    RouteExit::Failed(msg)
+}
+
+// Sends in a row that can fail before the route is restarted
+const MAX_SEND_FAILURES: u32 = 10;
+
+// How long an async route gets to stop before it is aborted
+const ROUTE_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Reply from an app on its way back to VRChat
+pub struct AppReply {
+    pub route: String,
+    pub from_address: String,
+    pub buf: Vec<u8>,
+}
+
+// Sets the flag when dropped so helper threads stop with their route
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// Packet pushed from the listeners to every app route's queue
+#[derive(Clone)]
+pub struct RouterPacket {
+    // Decoded once by the listener and shared by every route
+    pub buf: SharedOscBuf,
+    // When the listener received the packet
+    pub received: Instant,
+}
+
+// Per-route packet filter, evaluated after the global packet filter
+fn route_filter(pf: &Option<PacketFilter>, buf: SharedOscBuf) -> Vec<SharedOscBuf> {
+    match pf {
+        Some(pf) if pf.enabled => filter_packet(pf, &buf),
+        _ => vec![buf],
+    }
+}
+
+pub fn send_app_reply(
+    reply_tx: &Sender<AppReply>,
+    app_name: &str,
+    from_address: SocketAddr,
+    buf: &[u8],
+) {
+    let _ = reply_tx.send(AppReply {
+        route: app_name.to_string(),
+        from_address: from_address.to_string(),
+        buf: buf.to_vec(),
+    });
+}
+
+// Receive replies on a sync route's socket
+fn route_app_replies(
+    sock: UdpSocket,
+    reply_tx: Sender<AppReply>,
+    stop: Arc<AtomicBool>,
+    app_name: String,
+    refused: Option<Arc<AtomicBool>>,
+) {
+    let _ = sock.set_read_timeout(Some(std::time::Duration::from_secs(1)));
+    let mut buf = [0u8; MTU];
+
+    while !stop.load(Ordering::Relaxed) {
+        match sock.recv_from(&mut buf) {
+            Ok((br, address)) => send_app_reply(&reply_tx, &app_name, address, &buf[..br]),
+            // A connected socket reports refused sends to whichever call comes first, pass them on to the route
+            Err(e) if is_refused(&e) => {
+                if let Some(ref refused) = refused {
+                    refused.store(true, Ordering::Relaxed);
+                }
+            }
+            Err(_e) => {}
+        }
+    }
+}
+
+/*
+    Shared outbound pipeline: filter app replies and send them to VRChat
+*/
+fn route_vrc_return(
+    reply_rx: Receiver<AppReply>,
+    pf: PacketFilter,
+    vrc_sock: UdpSocket,
+    vrc_target: SocketAddr,
+    route_event_tx: Sender<RouteEvent>,
+    debug_sender: Option<Sender<routedbg::DebugPacket>>,
+) {
+    // Only changes are reported, the app routes keep their own statuses
+    let mut last_error: Option<String> = None;
+
+    // Ends when every route has dropped its reply sender
+    for reply in reply_rx {
+        let buf = Arc::new(OscBuf::decode(&reply.buf));
+        let forward_bufs = filter_packet(&pf, &buf);
+
+        let mode = if forward_bufs.is_empty() {
+            routedbg::IncomingDebugMode::DROPPED
+        } else {
+            routedbg::IncomingDebugMode::ALLOWED
+        };
+
+        for b in forward_bufs {
+            let error = match vrc_sock.send_to(&b.bytes, vrc_target) {
+                Ok(_) => None,
+                Err(_e) => Some(format!("Failed to send {} reply to VRChat: {}", reply.route, _e)),
+            };
+            if error != last_error {
+                let _ = route_event_tx.send(RouteEvent::ReturnError(error.clone()));
+                last_error = error;
+            }
+        }
+
+        if let Some(ref dbgs) = debug_sender {
+            routedbg::send_retdbg_packet(
+                dbgs,
+                reply.route,
+                reply.from_address,
+                &buf.bytes,
+                buf.pkt.clone(),
+                mode,
+            );
+        }
+    }
+}
+
+/*
+    Per-route packet processing shared by the sync and async route tasks.
+    Route filter -> Deduplication -> Rate limit -> Address rewrite -> Send
+*/
+struct RouteState {
     ai: i64,
     app: VORConfig,
+    rhp: SocketAddr,
+    // Destination shown in debug packets
+    to_address: String,
+    app_stat_tx: Sender<VORAppIdentifier>,
+    route_event_tx: Sender<RouteEvent>,
     debug_sender: Option<Sender<routedbg::DebugPacket>>,
-    _debug_out_config: Option<routedbg::VORDebugOptions>,
-) {
-    let rhp = format!("{}:{}", app.app_host, app.app_port);
+    reply_tx: Option<Sender<AppReply>>,
+    rewriter: Option<AddressRewriter>,
+    limiter: Option<RouteLimiter>,
+    dedup: Option<RouteDedup>,
+    // Set when the route sends over TCP, WebSocket or a Unix socket instead of UDP
+    sink: Option<RouteSink>,
+    stats: StatsReporter<RouteStats>,
+    queue: Arc<RouteQueue>,
+    // Set while the queue is over its high-water mark
+    queue_warning: bool,
+    // Set in health mode (UDP routes that detect when the app is not listening)
+    health: Option<RouteHealth>,
+    // Sends that failed since the last one that didn't
+    send_failures: u32,
+    last_send_error: String,
+}
+
+impl RouteState {
+    // The error is sent as the app's status if the route can't start
+    fn new(opts: RouteOptions, rhp: SocketAddr, queue: Arc<RouteQueue>) -> Result<Self, RouteExit> {
+        let RouteOptions {
+            ai,
+            app,
+            app_stat_tx,
+            route_event_tx,
+            debug_sender,
+            reply_tx,
+        } = opts;
+        let rewriter = match app.rewrite.as_ref().map(AddressRewriter::new).transpose() {
+            Ok(r) => r,
+            Err(_e) => {
+                let _ = app_stat_tx.send(app_error(
+                    ai,
+                    -5,
+                    format!("Invalid rewrite regex: {}", _e),
+                ));
+                return Err(RouteExit::Invalid);
+            }
+        };
+        let limiter = app.rate_limit.as_ref().map(RouteLimiter::new);
+        let dedup = app.dedup.as_ref().map(RouteDedup::new);
+        let sink = match app.transport {
+            Transport::Udp => None,
+            Transport::Tcp => Some(RouteSink::Tcp(TcpSink::new(
+                ai,
+                app.app_name.clone(),
+                rhp,
+                app_stat_tx.clone(),
+                reply_tx.clone(),
+            ))),
+            Transport::WebSocket => Some(RouteSink::WebSocket(WsSink::new(
+                ai,
+                rhp,
+                app.ws_format,
+                app_stat_tx.clone(),
+            ))),
+            Transport::UnixDgram => match UnixSink::new(app.unix_path.clone()) {
+                Ok(unix) => Some(RouteSink::UnixDgram(unix)),
+                Err(_e) => {
+                    return Err(route_failed(
+                        &app_stat_tx,
+                        ai,
+                        -2,
+                        format!("Failed to create app Unix socket: {}", _e),
+                    ));
+                }
+            },
+        };
+        let to_address = match sink {
+            Some(RouteSink::UnixDgram(ref unix)) => unix.path().to_string(),
+            _ => rhp.to_string(),
+        };
+        let health = if health_mode(&app, &rhp) {
+            Some(RouteHealth::new(rhp))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            ai,
+            rhp,
+            to_address,
+            app,
+            app_stat_tx,
+            route_event_tx,
+            debug_sender,
+            reply_tx,
+            rewriter,
+            limiter,
+            dedup,
+            sink,
+            stats: StatsReporter::new(),
+            queue,
+            queue_warning: false,
+            health,
+            send_failures: 0,
+            last_send_error: String::new(),
+        })
+    }
+
+    fn send_status(&self, status: VORAppStatus) {
+        let _ = self.app_stat_tx.send(VORAppIdentifier {
+            index: self.ai,
+            status,
+        });
+    }
+
+    // TCP and WebSocket routes report their own connection status
+    fn started(&self) {
+        if !self.sink.as_ref().is_some_and(|s| s.reports_status()) {
+            self.send_status(VORAppStatus::Running);
+        }
+    }
+
+    fn stop(&mut self) {
+        self.close();
+        self.send_status(VORAppStatus::Stopped);
+    }
+
+    // Close the sink first so its status can't arrive after the route's last status
+    fn close(&mut self) {
+        self.sink = None;
+        self.check_queue();
+        if let Some(stats) = self.stats.flush() {
+            self.send_status(VORAppStatus::Stats(stats));
+        }
+        if self.queue_warning {
+            self.send_warning(None);
+        }
+    }
+
+    // Set once sends keep failing. The route exits so the router can restart it.
+    fn failure(&self) -> Option<RouteExit> {
+        if self.send_failures < MAX_SEND_FAILURES {
+            return None;
+        }
+        Some(RouteExit::Failed(format!(
+            "{} sends failed in a row: {}",
+            self.send_failures, self.last_send_error
+        )))
+    }
+
+    // Copy the queue's counters and raise or clear the high-water warning
+    fn check_queue(&mut self) {
+        let stats = self.queue.stats();
+        self.stats.stats.queue_dropped = stats.dropped;
+        self.stats.stats.queue_coalesced = stats.coalesced;
+
+        let len = self.queue.len();
+        if !self.queue_warning && len >= self.queue.high_water() {
+            self.queue_warning = true;
+            self.send_warning(Some(format!(
+                "Queue {}/{} full, app is falling behind",
+                len,
+                self.queue.size()
+            )));
+        } else if self.queue_warning && len <= self.queue.low_water() {
+            self.queue_warning = false;
+            self.send_warning(None);
+        }
+    }
+
+    fn send_warning(&self, warning: Option<String>) {
+        self.send_status(VORAppStatus::Warning(warning));
+    }
+
+    fn listening_changed(&self, listening: bool) {
+        if listening {
+            self.send_status(VORAppStatus::Running);
+        } else {
+            self.send_status(VORAppStatus::NotListening);
+        }
+    }
+
+    // Periodic check if the app is listening (health mode only)
+    fn check_health(&mut self) {
+        if let Some(listening) = self.health.as_mut().and_then(|h| h.check()) {
+            self.listening_changed(listening);
+        }
+    }
+
+    // Refused sends and receives in health mode change the app's status instead of being errors. Returns true if handled.
+    fn health_error(&mut self, e: &std::io::Error) -> bool {
+        let health = match self.health {
+            Some(ref mut h) if is_refused(e) => h,
+            _ => return false,
+        };
+        if let Some(listening) = health.socket_error(e) {
+            self.listening_changed(listening);
+        }
+        true
+    }
+
+    // A packet from the listeners was sent to the app
+    fn routed(&mut self, received: Instant) {
+        self.stats.stats.latency.observe(received.elapsed());
+    }
+
+    // Buffers from the router that should be sent now
+    fn route_buffers(&mut self, b: SharedOscBuf) -> Vec<SharedOscBuf> {
+        self.stats.stats.received += 1;
+        let mut bufs = route_filter(&self.app.pf, b);
+        if bufs.is_empty() {
+            self.stats.stats.filtered += 1;
+        }
+        if let Some(ref dedup) = self.dedup {
+            let (send, unchanged): (Vec<SharedOscBuf>, Vec<SharedOscBuf>) =
+                bufs.into_iter().partition(|b| dedup.check(b));
+            for b in unchanged {
+                self.stats.stats.unchanged += 1;
+                self.dropped(&b, "Unchanged value");
+            }
+            bufs = send;
+        }
+        match self.limiter {
+            Some(ref mut limiter) => bufs.into_iter().filter_map(|b| limiter.limit(b)).collect(),
+            None => bufs,
+        }
+    }
+
+    // Coalesced buffers whose window ended
+    fn flush_buffers(&mut self) -> Vec<SharedOscBuf> {
+        match self.limiter {
+            Some(ref mut limiter) => limiter.flush(),
+            None => vec![],
+        }
+    }
+
+    fn report_stats(&mut self) {
+        if let Some(stats) = self.limiter.as_mut().and_then(|l| l.take_stats_update()) {
+            let _ = self.route_event_tx.send(RouteEvent::RateLimit(self.ai, stats));
+        }
+        if let Some(stats) = self.stats.take_update() {
+            self.send_status(VORAppStatus::Stats(stats));
+        }
+    }
+
+    fn next_wakeup(&self) -> Option<Instant> {
+        let limiter = self.limiter.as_ref().and_then(|l| l.next_wakeup());
+        let health = self.health.as_ref().map(|h| h.next_check());
+        [limiter, self.stats.next_report(), health].into_iter().flatten().min()
+    }
+
+    // The rewritten buffer if a rule matched
+    fn rewrite(&self, b: &OscBuf) -> Option<SharedOscBuf> {
+        self.rewriter.as_ref().and_then(|rw| rw.rewrite_buf(b))
+    }
+
+    fn sent(&self, b: &OscBuf, original: Option<&OscBuf>) {
+        if let Some(ref dbgs) = self.debug_sender {
+            routedbg::send_outdbg_packet(
+                dbgs,
+                self.app.app_name.clone(),
+                self.to_address.clone(),
+                &b.bytes,
+                b.pkt.clone(),
+                original.and_then(|o| o.pkt.clone()),
+                routedbg::OutgoingDebugMode::SENT,
+            );
+        }
+    }
+
+    fn dropped(&self, b: &OscBuf, reason: &str) {
+        if let Some(ref dbgs) = self.debug_sender {
+            routedbg::send_outdbg_packet(
+                dbgs,
+                self.app.app_name.clone(),
+                self.to_address.clone(),
+                &b.bytes,
+                b.pkt.clone(),
+                None,
+                routedbg::OutgoingDebugMode::DROPPED(reason.to_string()),
+            );
+        }
+    }
+
+    fn send_failed(&mut self, _e: std::io::Error) {
+        self.send_failures += 1;
+        self.last_send_error = format!("Failed to send VRC OSC buffer to app: {}", _e);
+        let _ = self.app_stat_tx.send(app_error(self.ai, -3, self.last_send_error.clone()));
+    }
+
+    // original is the buffer before it was rewritten
+    fn send_result(&mut self, res: std::io::Result<usize>, b: &OscBuf, original: Option<&OscBuf>) {
+        match res {
+            Ok(_bs) => {
+                self.send_failures = 0;
+                self.stats.stats.sent(b.bytes.len());
+                // Dedup compares the values before they're rewritten
+                if let Some(ref mut dedup) = self.dedup {
+                    dedup.mark_sent(original.unwrap_or(b));
+                }
+                self.sent(b, original);
+            }
+            Err(_e) => {
+                self.stats.stats.send_errors += 1;
+                if !self.health_error(&_e) {
+                    self.send_failed(_e);
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, sock: &UdpSocket, b: SharedOscBuf) {
+        let rewritten = self.rewrite(&b);
+        let out = rewritten.as_ref().unwrap_or(&b);
+        let res = match self.sink {
+            Some(ref sink) => sink.send(out).map(|_| out.bytes.len()),
+            // Route buffer. The socket is connected to the app in health mode.
+            None if self.health.is_some() => sock.send(&out.bytes),
+            None => sock.send_to(&out.bytes, self.rhp),
+        };
+        self.send_result(res, out, rewritten.as_ref().map(|_| &*b));
+    }
+
+    async fn send_async(&mut self, sock: &tokio::net::UdpSocket, b: SharedOscBuf) {
+        let rewritten = self.rewrite(&b);
+        let out = rewritten.as_ref().unwrap_or(&b);
+        let res = match self.sink {
+            Some(ref sink) => sink.send(out).map(|_| out.bytes.len()),
+            // Route buffer. The socket is connected to the app in health mode.
+            None if self.health.is_some() => sock.send(&out.bytes).await,
+            None => sock.send_to(&out.bytes, self.rhp).await,
+        };
+        self.send_result(res, out, rewritten.as_ref().map(|_| &*b));
+    }
+}
+
+// Resolve the app's host once at route start. Failures are sent as the app's status.
+fn resolve_app_target(
+    app_stat_tx: &Sender<VORAppIdentifier>,
+    ai: i64,
+    app: &VORConfig,
+) -> Result<SocketAddr, RouteExit> {
+    // Unix socket routes don't send to the app host. The route's UDP socket is still bound on IPv4.
+    if app.transport == Transport::UnixDgram {
+        return Ok((Ipv4Addr::UNSPECIFIED, 0).into());
+    }
+
+    resolve_target(&host_port(&app.app_host, &app.app_port)).map_err(|_e| {
+        route_failed(app_stat_tx, ai, -6, format!("Failed to resolve app host: {}", _e))
+    })
+}
+
+/*
+    Health mode connects the route's UDP socket to the app so the app not listening shows up as refused sends.
+    Multicast and broadcast targets never refuse packets.
+*/
+fn health_mode(app: &VORConfig, rhp: &SocketAddr) -> bool {
+    let group = match rhp.ip() {
+        IpAddr::V4(ip) => ip.is_multicast() || ip.is_broadcast() || app.broadcast,
+        IpAddr::V6(ip) => ip.is_multicast(),
+    };
+    app.health_check && app.transport == Transport::Udp && !group
+}
+
+// Bind the route's socket with the options its app host needs. Failures are sent as the app's status.
+fn bind_app_socket(
+    app_stat_tx: &Sender<VORAppIdentifier>,
+    ai: i64,
+    app: &VORConfig,
+    rhp: &SocketAddr,
+) -> Result<UdpSocket, RouteExit> {
+    UdpSocket::bind(unspecified_for(rhp))
+        .and_then(|s| {
+            configure_udp_target(&s, rhp, &app.multicast_ttl, app.multicast_loop, app.broadcast)?;
+            if health_mode(app, rhp) {
+                s.connect(rhp)?;
+            }
+            Ok(s)
+        })
+        .map_err(|_e| route_failed(app_stat_tx, ai, -2, format!("Failed to bind app UdpSocket: {}", _e)))
+}
+
+// What a route task needs besides its queue and shutdown signal
+struct RouteOptions {
+    ai: i64,
+    app: VORConfig,
+    app_stat_tx: Sender<VORAppIdentifier>,
+    route_event_tx: Sender<RouteEvent>,
+    debug_sender: Option<Sender<routedbg::DebugPacket>>,
+    // Only routes with the return path enabled get a reply sender
+    reply_tx: Option<Sender<AppReply>>,
+}
+
+fn route_app(queue: Arc<RouteQueue>, mut router_rx: RouteSignalReceiver, opts: RouteOptions) -> Result<(), RouteExit> {
+    let ai = opts.ai;
     //let lhp = format!("{}:{}", app.bind_host, app.bind_port);
-    let sock = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
-        Ok(s) => s,
+    let rhp = resolve_app_target(&opts.app_stat_tx, ai, &opts.app)?;
+    // Close app route thread because app failed to bind
+    let sock = bind_app_socket(&opts.app_stat_tx, ai, &opts.app, &rhp)?;
+    let mut route = RouteState::new(opts, rhp, queue.clone())?;
+    //println!("[*] OSC App: [{}] Route Initialized..", app.app_name);
+    route.started();
+    //let r = router_rx.recv_timeout(std::time::Duration::from_secs(1));
+
+    // Reply receiver thread stops when this route exits
+    // TCP routes receive replies on their connection
+    let _reply_stop = StopOnDrop(Arc::new(AtomicBool::new(false)));
+    if let Some(reply_tx) = route.reply_tx.clone().filter(|_| route.sink.is_none()) {
+        if let Ok(reply_sock) = sock.try_clone() {
+            let stop = _reply_stop.0.clone();
+            let app_name = route.app.app_name.clone();
+            let refused = route.health.as_ref().map(|h| h.refused_reply_flag());
+            thread::spawn(move || route_app_replies(reply_sock, reply_tx, stop, app_name, refused));
+        }
+    }
+
+    /*
+        The route thread blocks until a packet, a shutdown signal or a coalescing/stats wakeup arrives.
+        A single threaded runtime owned by this thread only does the waiting so routing doesn't share a scheduler with other routes.
+    */
+    let rt = match tokio::runtime::Builder::new_current_thread().enable_time().build() {
+        Ok(rt) => rt,
         Err(_e) => {
-            let _ = app_stat_tx_at.send(app_error(
+            return Err(route_failed(
+                &route.app_stat_tx,
                 ai,
                 -2,
-                format!("Failed to bind app UdpSocket: {}", _e),
+                format!("Failed to start route runtime: {}", _e),
             ));
-            return; // Close app route thread because app failed to bind
         }
     };
-    //println!("[*] OSC App: [{}] Route Initialized..", app.app_name);
-    let _ = app_stat_tx_at.send(VORAppIdentifier {
-        index: ai,
-        status: VORAppStatus::Running,
-    });
-    //let r = router_rx.recv_timeout(std::time::Duration::from_secs(1));
 
-    loop {
-        ////println!("router_rx start");
-        match router_rx.try_recv() {
-            Ok(signal) => {
-                ////println!("[!] signal: {}", signal);
-                if signal {
-                    let _ = app_stat_tx_at.send(VORAppIdentifier {
-                        index: ai,
-                        status: VORAppStatus::Stopped,
-                    });
+    rt.block_on(async {
+        loop {
+            // Send coalesced buffers whose window ended
+            for b in route.flush_buffers() {
+                route.send(&sock, b);
+            }
+            if let Some(failure) = route.failure() {
+                route.close();
+                return Err(failure);
+            }
+            route.report_stats();
+            route.check_health();
+            let wakeup = route.next_wakeup();
+            let deadline = tokio::time::Instant::from_std(wakeup.unwrap_or_else(Instant::now));
+
+            tokio::select! {
+                // Shutdown signal (or the router dropped this route)
+                _ = router_rx.recv() => {
+                    route.stop();
                     //println!("[!] Send Stopped status");
-                    return;
+                    return Ok(());
                 }
-            }
-            _ => { /*//println!("[!] Try recv errors")*/ }
-        }
-        ////println!("router_rx done");
-        // Get vrc OSC buffer
-        match rx.try_recv() {
-            Ok(b) => {
-                // Route buffer
-                match sock.send_to(&b, &rhp) {
-                    Ok(_bs) => {
-                        if let Some(ref dbgs) = debug_sender {
-                            // Try to get parsed packet
-                            if let Ok(pkt) = rosc::decoder::decode_udp(&b) {
-                                routedbg::send_outdbg_packet(
-                                    dbgs,
-                                    app.app_name.clone(),
-                                    rhp.clone(),
-                                    &b,
-                                    Some(pkt.1),
-                                );
-                            } else {
-                                routedbg::send_outdbg_packet(
-                                    dbgs,
-                                    app.app_name.clone(),
-                                    rhp.clone(),
-                                    &b,
-                                    None,
-                                );
-                            }
+                // Get vrc OSC buffer
+                res = queue.recv() => match res {
+                    Some(p) => {
+                        route.check_queue();
+                        let bufs = route.route_buffers(p.buf);
+                        let routed = !bufs.is_empty();
+                        for b in bufs {
+                            route.send(&sock, b);
+                        }
+                        if routed {
+                            route.routed(p.received);
                         }
                     }
-                    Err(_e) => {
-                        let _ = app_stat_tx_at.send(app_error(
-                            ai,
-                            -3,
-                            format!("Failed to send VRC OSC buffer to app: {}", _e),
-                        ));
-                    }
-                }
-            }
-            Err(TryRecvError::Empty) => continue,
-            Err(TryRecvError::Lagged(_)) => continue,
-            Err(TryRecvError::Closed) => {
-                // VRC OSC BUFFER CHANNEL DIED SO KILL ROUTE THREAD
-                let _ = app_stat_tx_at.send(VORAppIdentifier {
-                    index: ai,
-                    status: VORAppStatus::Stopped,
-                });
+                    None => {
+                        // VRC OSC BUFFER QUEUE CLOSED SO KILL ROUTE THREAD
+                        route.stop();
 
-                return;
+                        return Ok(());
+                    }
+                },
+                // Coalescing window ended or counters are due
+                _ = tokio::time::sleep_until(deadline), if wakeup.is_some() => {}
             }
-        };
-    }
+        }
+    })
 }
 
-async fn route_app_async(
-    mut rx: bcst_Receiver<Vec<u8>>,
-    router_rx: Receiver<bool>,
-    app_stat_tx_at: Sender<VORAppIdentifier>,
-    ai: i64,
-    app: VORConfig,
-    debug_sender: Option<Sender<routedbg::DebugPacket>>,
-    _debug_out_config: Option<routedbg::VORDebugOptions>,
-) {
-    let rhp = format!("{}:{}", app.app_host, app.app_port);
+async fn route_app_async(queue: Arc<RouteQueue>, mut router_rx: RouteSignalReceiver, opts: RouteOptions) -> Result<(), RouteExit> {
+    let ai = opts.ai;
     //let lhp = format!("{}:{}", app.bind_host, app.bind_port);
-    let sock = match tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+    let rhp = resolve_app_target(&opts.app_stat_tx, ai, &opts.app)?;
+    // Close app route thread because app failed to bind
+    let sock = bind_app_socket(&opts.app_stat_tx, ai, &opts.app, &rhp)?;
+    let sock = match sock
+        .set_nonblocking(true)
+        .and_then(|_| tokio::net::UdpSocket::from_std(sock))
+    {
         Ok(s) => s,
         Err(_e) => {
-            let _ = app_stat_tx_at.send(app_error(
+            // Close app route thread because app failed to bind
+            return Err(route_failed(
+                &opts.app_stat_tx,
                 ai,
                 -2,
                 format!("Failed to bind app UdpSocket: {}", _e),
             ));
-            return; // Close app route thread because app failed to bind
         }
     };
+    let mut route = RouteState::new(opts, rhp, queue.clone())?;
     //println!("[*] OSC App: [{}] Route Initialized..", app.app_name);
-    let _ = app_stat_tx_at.send(VORAppIdentifier {
-        index: ai,
-        status: VORAppStatus::Running,
-    });
+    route.started();
     //let r = router_rx.recv_timeout(std::time::Duration::from_secs(1));
 
+    let mut reply_buf = [0u8; MTU];
+    // TCP routes receive replies on their connection
+    let udp_replies = route.reply_tx.is_some() && route.sink.is_none();
+
     loop {
-        ////println!("router_rx start");
-        match router_rx.try_recv() {
-            Ok(signal) => {
-                ////println!("[!] signal: {}", signal);
-                if signal {
-                    let _ = app_stat_tx_at.send(VORAppIdentifier {
-                        index: ai,
-                        status: VORAppStatus::Stopped,
-                    });
-                    //println!("[!] Send Stopped status");
-                    return;
-                }
+        // Send coalesced buffers whose window ended
+        for b in route.flush_buffers() {
+            route.send_async(&sock, b).await;
+        }
+        if let Some(failure) = route.failure() {
+            route.close();
+            return Err(failure);
+        }
+        route.report_stats();
+        route.check_health();
+        let wakeup = route.next_wakeup();
+
+        tokio::select! {
+            // Shutdown signal (or the router dropped this route)
+            _ = router_rx.recv() => {
+                route.stop();
+                return Ok(());
             }
-            _ => { /*//println!("[!] Try recv errors")*/ }
-        }
-        ////println!("router_rx done");
-        // Get vrc OSC buffer
-        // route_main thread should abort this await on async runtime shutdown when threads are aborted. So don't have to worry about thread blocking with recv
-        match rx.recv().await {
-            Ok(b) => {
-                // Route buffer
-
-                match sock.send_to(&b, &rhp).await {
-                    Ok(_bs) => {
-                        if let Some(ref dbgs) = debug_sender {
-                            // Try to get parsed packet
-                            if let Ok(pkt) = rosc::decoder::decode_udp(&b) {
-                                routedbg::send_outdbg_packet(
-                                    dbgs,
-                                    app.app_name.clone(),
-                                    rhp.clone(),
-                                    &b,
-                                    Some(pkt.1),
-                                );
-                            } else {
-                                routedbg::send_outdbg_packet(
-                                    dbgs,
-                                    app.app_name.clone(),
-                                    rhp.clone(),
-                                    &b,
-                                    None,
-                                );
-                            }
-                        }
+            // Get vrc OSC buffer
+            res = queue.recv() => match res {
+                Some(p) => {
+                    route.check_queue();
+                    let bufs = route.route_buffers(p.buf);
+                    let routed = !bufs.is_empty();
+                    for b in bufs {
+                        route.send_async(&sock, b).await;
                     }
-                    Err(_e) => {
-                        let _ = app_stat_tx_at.send(app_error(
-                            ai,
-                            -3,
-                            format!("Failed to send VRC OSC buffer to app: {}", _e),
-                        ));
+                    if routed {
+                        route.routed(p.received);
                     }
                 }
-            }
-            Err(RecvError::Lagged(_)) => continue,
-            Err(RecvError::Closed) => {
-                // VRC OSC BUFFER CHANNEL DIED SO KILL ROUTE THREAD
-                let _ = app_stat_tx_at.send(VORAppIdentifier {
-                    index: ai,
-                    status: VORAppStatus::Stopped,
-                });
+                None => {
+                    // VRC OSC BUFFER QUEUE CLOSED SO KILL ROUTE THREAD
+                    route.stop();
 
-                return;
-            }
-        };
+                    return Ok(());
+                }
+            },
+            // Replies from the app back to VRChat
+            res = sock.recv_from(&mut reply_buf), if udp_replies => match res {
+                Ok((br, address)) => {
+                    send_app_reply(route.reply_tx.as_ref().unwrap(), &route.app.app_name, address, &reply_buf[..br]);
+                }
+                // A connected socket reports refused sends to whichever call comes first
+                Err(e) => {
+                    route.health_error(&e);
+                }
+            },
+            // Coalescing window ended or counters are due
+            _ = tokio::time::sleep_until(tokio::time::Instant::from_std(wakeup.unwrap_or_else(Instant::now))), if wakeup.is_some() => {}
+        }
     }
 }
 
-fn parse_vrc_osc(
-    bcst_tx: bcst_Sender<Vec<u8>>,
-    router_rx: Receiver<bool>,
+// What every listener thread shares
+#[derive(Clone)]
+struct ListenerOptions {
+    queues: RouteQueues,
     pf: PacketFilter,
-    vrc_sock: UdpSocket,
-    _debug_incoming_config: Option<routedbg::VORDebugOptions>,
+    app_stat_tx: Sender<VORAppIdentifier>,
     debug_sender: Option<Sender<routedbg::DebugPacket>>,
-) {
-    // Here sending the decoded packet's buffer instead of the UDP buffer
-    // because some OSC libraries cant parse OSC packets with trailing NULL bytes.
-    // However if malformed OSC packets are relayed due to PF allowing bad packets through they will be sent with a full MTU (NULL BYTES PADDED)
+    capture: Capture,
+}
+
+fn parse_vrc_osc(opts: ListenerOptions, router_rx: Receiver<bool>, vrc_sock: UdpSocket, listener: String) {
+    let ListenerOptions {
+        queues,
+        pf,
+        app_stat_tx,
+        debug_sender,
+        capture,
+    } = opts;
+    let mut stats = StatsReporter::<ListenerStats>::new();
+    let local_addr = vrc_sock.local_addr().unwrap_or_else(|_| (Ipv4Addr::UNSPECIFIED, 0).into());
+    let report_stats = |stats: Option<ListenerStats>| {
+        if let Some(s) = stats {
+            let _ = app_stat_tx.send(VORAppIdentifier {
+                index: -1,
+                status: VORAppStatus::Listener(listener.clone(), s),
+            });
+        }
+    };
+
     let mut buf = [0u8; MTU];
 
     loop {
@@ -233,42 +808,23 @@ fn parse_vrc_osc(
                     // If got bytes send them to routers otherwise restart loop
                     continue;
                 } else {
-                    // Packet Filtering
+                    let received = Instant::now();
+                    // Only the received bytes. Some OSC libraries cant parse OSC packets with trailing NULL bytes.
+                    let osc = Arc::new(OscBuf::decode(&buf[..br]));
 
-                    if pf.enabled {
-                        // PF enabled
-                        packet_filter(&pf, buf, &address.to_string(), &bcst_tx, &debug_sender);
-
-                    } else {
-                        // PF disabled
-                        bcst_tx.send(buf.to_vec()).unwrap();
-                        if let Some(ref dbgs) = debug_sender {
-                            // Try to get parsed packet
-                            if let Ok(pkt) = rosc::decoder::decode_udp(&buf) {
-                                routedbg::send_indbg_packet(
-                                    dbgs,
-                                    &buf,
-                                    Some(pkt.1),
-                                    address.to_string(),
-                                    routedbg::IncomingDebugMode::ALLOWED,
-                                );
-                            } else {
-                                // Still ALLOWED because PF is disabled
-                                routedbg::send_indbg_packet(
-                                    dbgs,
-                                    &buf,
-                                    None,
-                                    address.to_string(),
-                                    routedbg::IncomingDebugMode::ALLOWED,
-                                );
-                            }
-                        }
+                    // Packet Filtering
+                    let bufs = packet_filter(&pf, &osc, &address.to_string(), &listener, &debug_sender, &mut stats.stats);
+                    capture.record(&listener, address, local_addr, &osc.bytes, !bufs.is_empty(), received);
+                    for b in bufs {
+                        queues.push(RouterPacket { buf: b, received });
                     }
+                    report_stats(stats.take_update());
 
                     match router_rx.try_recv() {
                         Ok(sig) => {
                             if sig {
                                 //println!("[!] VRC OSC thread shutdown");
+                                report_stats(stats.flush());
                                 return;
                             }
                         }
@@ -278,10 +834,12 @@ fn parse_vrc_osc(
             }
             Err(_e) => {
                 ////println!("UDPSOCKERR: {}", _e);
+                report_stats(stats.take_update());
                 match router_rx.try_recv() {
                     Ok(sig) => {
                         if sig {
                             //println!("[!] VRC OSC thread shutdown");
+                            report_stats(stats.flush());
                             return;
                         }
                     }
@@ -292,138 +850,487 @@ fn parse_vrc_osc(
     } // loop
 }
 
+// Shutdown signal sent to a route. Sync route threads block on it with their packets.
+type RouteSignalSender = tokio::sync::mpsc::UnboundedSender<bool>;
+type RouteSignalReceiver = tokio::sync::mpsc::UnboundedReceiver<bool>;
+
+// Handle to a running app route so it can be stopped at runtime
+struct RouteHandle {
+    router_tx: RouteSignalSender,
+    thread: Option<thread::JoinHandle<()>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+    // Failures of an earlier route with the same id are ignored
+    generation: u64,
+    // Config the route is restarted with
+    app: VORConfig,
+    started: Instant,
+}
+
+// Everything a route needs from the router to be spawned
+struct RouteContext {
+    queues: RouteQueues,
+    // Size of each route's queue (VOR Buffer Queue Size)
+    queue_size: usize,
+    app_stat_tx: Sender<VORAppIdentifier>,
+    route_event_tx: Sender<RouteEvent>,
+    debug_sender: Option<Sender<routedbg::DebugPacket>>,
+    reply_tx: Sender<AppReply>,
+    metrics: Option<Metrics>,
+    // Routes report failures to the router loop
+    event_tx: Sender<RouterEvent>,
+    generation: u64,
+}
+
+impl RouteContext {
+    fn spawn_route(
+        &mut self,
+        async_rt: Option<&tokio::runtime::Runtime>,
+        app: VORConfig,
+        id: i64,
+    ) -> RouteHandle {
+        let (router_tx, router_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.generation += 1;
+        let generation = self.generation;
+        let route_app_config = app.clone();
+        let event_tx = self.event_tx.clone();
+        let route_failed = move |exit: Result<(), RouteExit>| {
+            if let Err(RouteExit::Failed(e)) = exit {
+                let _ = event_tx.send(RouterEvent::RouteFailed(id, generation, e));
+            }
+        };
+
+        if let Some(ref metrics) = self.metrics {
+            metrics.add_route(id, &app.app_name);
+        }
+
+        // The listeners start filling the route's queue right away
+        let queue = Arc::new(RouteQueue::new(self.queue_size, app.queue_policy));
+        self.queues.add(id, queue.clone());
+
+        let opts = RouteOptions {
+            ai: id,
+            app_stat_tx: self.app_stat_tx.clone(),
+            route_event_tx: self.route_event_tx.clone(),
+            debug_sender: self.debug_sender.clone(),
+            reply_tx: if app.vrc_return {
+                Some(self.reply_tx.clone())
+            } else {
+                None
+            },
+            app,
+        };
+        /*
+            Spawn app routers in the async runtime
+        */
+        if let Some(rt) = async_rt {
+            RouteHandle {
+                router_tx,
+                thread: None,
+                task: Some(rt.spawn(async move {
+                    route_failed(route_app_async(queue, router_rx, opts).await)
+                })),
+                generation,
+                app: route_app_config,
+                started: Instant::now(),
+            }
+        } else {
+            RouteHandle {
+                router_tx,
+                thread: Some(thread::spawn(move || route_failed(route_app(queue, router_rx, opts)))),
+                task: None,
+                generation,
+                app: route_app_config,
+                started: Instant::now(),
+            }
+        }
+    }
+
+    // Stop a single route and wait for it to exit. Its queue stops receiving packets.
+    fn stop_route(&self, async_rt: Option<&tokio::runtime::Runtime>, id: i64, handle: RouteHandle) {
+        self.queues.remove(id);
+        let _ = handle.router_tx.send(true);
+
+        if let (Some(task), Some(rt)) = (handle.task, async_rt) {
+            rt.block_on(stop_task(task));
+        }
+
+        if let Some(t) = handle.thread {
+            let _ = t.join();
+        }
+    }
+}
+
+// Wait for an async route to send its final status. A route stuck in a send is aborted.
+async fn stop_task(mut task: tokio::task::JoinHandle<()>) {
+    if tokio::time::timeout(ROUTE_STOP_TIMEOUT, &mut task).await.is_err() {
+        task.abort();
+        let _ = task.await;
+    }
+}
+
+// Router settings from the router config, packet filters and GUI
+pub struct RouterOptions {
+    // (Name, host:port) of each listener. The first is VRChat's.
+    pub listeners: Vec<(String, String)>,
+    pub vrc_target: String,
+    pub multicast_group: String,
+    pub pf: PacketFilter,
+    pub vrc_pf: PacketFilter,
+    // Size of each route's queue (VOR Buffer Queue Size)
+    pub queue_size: usize,
+    pub async_mode: bool,
+    pub oscquery: bool,
+    pub metrics_target: Option<String>,
+    pub debug_sender: Option<Sender<routedbg::DebugPacket>>,
+    // Record the listeners' datagrams to this file from the start
+    pub capture_path: Option<String>,
+}
+
+impl RouterOptions {
+    // Fails if the VOR buffer queue size isn't a number
+    pub fn new(config: &RouterConfig, pf: PacketFilter, vrc_pf: PacketFilter) -> Result<Self, String> {
+        let queue_size = config
+            .vor_buffer_size
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid VOR buffer queue size: {}", config.vor_buffer_size))?;
+        Ok(Self {
+            listeners: config.listener_targets(),
+            vrc_target: host_port(&config.vrc_host, &config.vrc_port),
+            multicast_group: config.multicast_group.clone(),
+            pf,
+            vrc_pf,
+            queue_size,
+            async_mode: config.async_mode,
+            oscquery: config.oscquery,
+            metrics_target: config.metrics_target(),
+            debug_sender: None,
+            capture_path: None,
+        })
+    }
+}
+
 pub fn route_main(
-    router_bind_target: String,
+    opts: RouterOptions,
+    configs: Vec<(VORConfig, i64)>,
     router_rx: Receiver<RouterMsg>,
     app_stat_tx: Sender<VORAppIdentifier>,
-    configs: Vec<(VORConfig, i64)>,
-    pf: PacketFilter,
-    vor_queue_size: usize,
-    async_mode: bool,
-    debug_route_channels: Option<Sender<routedbg::DebugPacket>>,
-    debug_config: Option<routedbg::VORDebugOptions>,
+    route_event_tx: Sender<RouteEvent>,
 ) {
-    // Bind UDP listening socket
-    let vrc_sock = match UdpSocket::bind(router_bind_target) {
-        Ok(s) => s,
+    let RouterOptions {
+        listeners,
+        vrc_target,
+        multicast_group,
+        pf,
+        vrc_pf,
+        queue_size,
+        async_mode,
+        oscquery,
+        metrics_target,
+        debug_sender,
+        capture_path,
+    } = opts;
+
+    // Metrics endpoint. Statuses and route events are recorded on their way to the GUI. The router still runs if it can't start.
+    let mut _metrics_server = None;
+    let mut metrics = None;
+    if let Some(target) = metrics_target {
+        let m = Metrics::default();
+        match resolve_target(&target).and_then(|a| MetricsServer::new(a, m.clone())) {
+            Ok(s) => {
+                _metrics_server = Some(s);
+                metrics = Some(m);
+            }
+            Err(_e) => println!("[-] Metrics: {}", _e),
+        }
+    }
+    let app_stat_tx = match metrics {
+        Some(ref m) => {
+            let (tee_tx, tee_rx): (Sender<VORAppIdentifier>, Receiver<VORAppIdentifier>) = mpsc::channel();
+            let m = m.clone();
+            thread::spawn(move || {
+                for status in tee_rx {
+                    m.record(&status);
+                    if app_stat_tx.send(status).is_err() {
+                        return;
+                    }
+                }
+            });
+            tee_tx
+        }
+        None => app_stat_tx,
+    };
+    let route_event_tx = match metrics {
+        Some(ref m) => {
+            let (tee_tx, tee_rx): (Sender<RouteEvent>, Receiver<RouteEvent>) = mpsc::channel();
+            let m = m.clone();
+            thread::spawn(move || {
+                for event in tee_rx {
+                    m.record_event(&event);
+                    if route_event_tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            });
+            tee_tx
+        }
+        None => route_event_tx,
+    };
+
+    // Resolve the VRChat target once for the life of the router
+    let vrc_target = match resolve_target(&vrc_target) {
+        Ok(a) => a,
         Err(_e) => {
-            let _ = app_stat_tx.send(app_error(-1, -1, "Failed to bind VOR socket.".to_string()));
+            let _ = app_stat_tx.send(app_error(-1, -1, format!("Failed to resolve VRChat host: {}", _e)));
             return;
         }
     };
 
-    // Setting this socket to timed blocking does not have a dramatic effect on message passing delays due to socket blocking
-    vrc_sock.set_nonblocking(false).unwrap();
-    let _ = vrc_sock.set_read_timeout(Some(std::time::Duration::from_secs(1)));
+    // Bind UDP listening sockets. The first listener is VRChat's and joins the multicast group.
+    let mut listener_socks = Vec::new();
+    for (i, (name, target)) in listeners.into_iter().enumerate() {
+        let bind_addr = match resolve_target(&target) {
+            Ok(a) => a,
+            Err(_e) => {
+                let _ = app_stat_tx.send(app_error(-1, -1, format!("Failed to resolve {} listener bind host: {}", name, _e)));
+                return;
+            }
+        };
 
-    // App route threads
-    let mut artc = Vec::new();
+        let vrc_sock = match bind_udp(bind_addr) {
+            Ok(s) => s,
+            Err(_e) => {
+                let _ = app_stat_tx.send(app_error(-1, -1, format!("Failed to bind {} listener socket.", name)));
+                return;
+            }
+        };
+
+        if i == 0 {
+            if let Err(_e) = join_multicast(&vrc_sock, &multicast_group) {
+                let _ = app_stat_tx.send(app_error(-1, -1, format!("Failed to join multicast group: {}", _e)));
+                return;
+            }
+        }
+
+        // Setting this socket to timed blocking does not have a dramatic effect on message passing delays due to socket blocking
+        vrc_sock.set_nonblocking(false).unwrap();
+        let _ = vrc_sock.set_read_timeout(Some(std::time::Duration::from_secs(1)));
+        listener_socks.push((name, vrc_sock));
+    }
+
+    // Bind socket for app replies returned to VRChat
+    let vrc_return_sock = match UdpSocket::bind(unspecified_for(&vrc_target)) {
+        Ok(s) => s,
+        Err(_e) => {
+            let _ = app_stat_tx.send(app_error(-1, -1, "Failed to bind VOR return socket.".to_string()));
+            return;
+        }
+    };
+
+    // Advertise the VRChat listener. The router still runs if OSCQuery can't start.
+    let mut _oscquery_server = None;
+    if oscquery {
+        let osc_addr = listener_socks[0].1.local_addr().map_err(|e| e.to_string());
+        match osc_addr.and_then(|a| OscQueryServer::new(a, &pf)) {
+            Ok(s) => _oscquery_server = Some(s),
+            Err(_e) => println!("[-] OSCQuery: {}", _e),
+        }
+    }
 
     /*
         Create async runtime
     */
     let mut async_rt: Option<tokio::runtime::Runtime> = None;
-    let mut async_threads = vec![];
     if async_mode {
         async_rt = Some(tokio::runtime::Runtime::new().unwrap());
     }
 
-    // Router message broadcast channels
-    let (bcst_tx, _bcst_rx) = broadcast::channel(vor_queue_size);
-
-    for (app, id) in configs {
-        let (router_tx, router_rx) = mpsc::channel();
-        artc.push(router_tx);
+    // Every route gets its own queue of the listeners' packets
+    let queues = RouteQueues::default();
 
-        // App status sender
-        let app_stat_tx_at = app_stat_tx.clone();
-        
-        // Create new RX for broadcast channel
-        let bcst_app_rx = bcst_tx.subscribe();
+    // App replies -> VRChat
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let return_event_tx = route_event_tx.clone();
+    let return_debug_sender = debug_sender.clone();
+    thread::spawn(move || {
+        route_vrc_return(
+            reply_rx,
+            vrc_pf,
+            vrc_return_sock,
+            vrc_target,
+            return_event_tx,
+            return_debug_sender,
+        );
+    });
 
-        let app_debug_sender_clone = debug_route_channels.clone();
-        let app_debug_config_clone = debug_config.clone();
-        /*
-            Spawn app routers in the async runtime
-        */
-        if async_mode {
-            async_threads.push(async_rt.as_ref().unwrap().spawn(route_app_async(
-                bcst_app_rx,
-                router_rx,
-                app_stat_tx_at,
-                id,
-                app,
-                app_debug_sender_clone,
-                app_debug_config_clone,
-            )));
-        } else {
-            thread::spawn(move || {
-                route_app(
-                    bcst_app_rx,
-                    router_rx,
-                    app_stat_tx_at,
-                    id,
-                    app,
-                    app_debug_sender_clone,
-                    app_debug_config_clone,
-                )
-            });
+    // GUI messages are forwarded so the router loop also wakes for failed routes and due restarts
+    let (event_tx, event_rx) = mpsc::channel();
+    let gui_event_tx = event_tx.clone();
+    thread::spawn(move || {
+        for msg in router_rx {
+            if gui_event_tx.send(RouterEvent::Msg(msg)).is_err() {
+                return;
+            }
         }
+        // The GUI dropped its sender without stopping the router
+        let _ = gui_event_tx.send(RouterEvent::Msg(RouterMsg::ShutdownAll));
+    });
+
+    let mut route_ctx = RouteContext {
+        queues: queues.clone(),
+        queue_size,
+        app_stat_tx: app_stat_tx.clone(),
+        route_event_tx: route_event_tx.clone(),
+        debug_sender: debug_sender.clone(),
+        reply_tx,
+        metrics,
+        event_tx,
+        generation: 0,
+    };
+    let mut supervisor = RouteSupervisor::default();
+
+    // App route threads
+    let mut routes: HashMap<i64, RouteHandle> = HashMap::new();
+
+    for (app, id) in configs {
+        routes.insert(id, route_ctx.spawn_route(async_rt.as_ref(), app, id));
     }
-    drop(_bcst_rx); // Dont need this rx
 
-    let (osc_parse_tx, osc_parse_rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+    // Capture file recorder, fed by the listeners
+    let capture = Capture::default();
+    let mut recorder = Recorder::new(capture.clone(), route_event_tx.clone());
+    if let Some(ref path) = capture_path {
+        recorder.start(path);
+    }
 
-    thread::spawn(move || {
-        parse_vrc_osc(
-            bcst_tx,
-            osc_parse_rx,
-            pf,
-            vrc_sock,
-            debug_config,
-            debug_route_channels,
-        );
-    });
+    // One parse thread per listener, all feeding the route queues
+    let listener_opts = ListenerOptions {
+        queues: queues.clone(),
+        pf,
+        app_stat_tx: app_stat_tx.clone(),
+        debug_sender,
+        capture,
+    };
+    let mut osc_parse_txs: Vec<Sender<bool>> = Vec::new();
+    for (name, vrc_sock) in listener_socks {
+        let (osc_parse_tx, osc_parse_rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+        osc_parse_txs.push(osc_parse_tx);
+
+        let opts = listener_opts.clone();
+        thread::spawn(move || parse_vrc_osc(opts, osc_parse_rx, vrc_sock, name));
+    }
     //println!("[+] Started VRChat OSC Router.");
 
     // Listen for GUI events
-    // Add dynamic route handling here probably
     /*
         Handle Removing/Adding/Modifying Routes
     */
     loop {
-        match router_rx.recv().unwrap() {
-            RouterMsg::ShutdownAll => {
+        // Wait for the next event or until the next failed route is due to restart
+        let event = match supervisor.next_restart() {
+            Some(at) => event_rx.recv_timeout(at.saturating_duration_since(Instant::now())).ok(),
+            None => event_rx.recv().ok(),
+        };
+
+        match event {
+            // Restart timer
+            None => {}
+            Some(RouterEvent::RouteFailed(id, generation, error)) if routes.get(&id).is_some_and(|h| h.generation == generation) => {
+                if let Some(handle) = routes.remove(&id) {
+                    let ran_for = handle.started.elapsed();
+                    let app = handle.app.clone();
+                    route_ctx.stop_route(async_rt.as_ref(), id, handle);
+                    let stats = supervisor.failed(id, app, error, ran_for);
+                    let _ = app_stat_tx.send(VORAppIdentifier {
+                        index: id,
+                        status: VORAppStatus::Restarting(stats),
+                    });
+                }
+            }
+            // Failure of a route that was already stopped or replaced
+            Some(RouterEvent::RouteFailed(..)) => {}
+            Some(RouterEvent::Msg(RouterMsg::AddRoute(app, id))) => {
+                supervisor.cancel(id);
+                // Replace the route if it already exists
+                if let Some(handle) = routes.remove(&id) {
+                    route_ctx.stop_route(async_rt.as_ref(), id, handle);
+                }
+                routes.insert(id, route_ctx.spawn_route(async_rt.as_ref(), app, id));
+            }
+            Some(RouterEvent::Msg(RouterMsg::RemoveRoute(id))) => {
+                supervisor.remove(id);
+                if let Some(handle) = routes.remove(&id) {
+                    route_ctx.stop_route(async_rt.as_ref(), id, handle);
+                }
+                if let Some(ref metrics) = route_ctx.metrics {
+                    metrics.remove_route(id);
+                }
+            }
+            Some(RouterEvent::Msg(RouterMsg::UpdateRoute(app, id))) => {
+                // Only restart the edited route. Other routes keep routing.
+                let restarting = supervisor.cancel(id);
+                if let Some(handle) = routes.remove(&id) {
+                    route_ctx.stop_route(async_rt.as_ref(), id, handle);
+                    routes.insert(id, route_ctx.spawn_route(async_rt.as_ref(), app, id));
+                } else if restarting {
+                    routes.insert(id, route_ctx.spawn_route(async_rt.as_ref(), app, id));
+                }
+            }
+            Some(RouterEvent::Msg(RouterMsg::PauseRoute(id))) => {
+                let restarting = supervisor.cancel(id);
+                let handle = routes.remove(&id);
+                let paused = handle.is_some() || restarting;
+                if let Some(handle) = handle {
+                    route_ctx.stop_route(async_rt.as_ref(), id, handle);
+                }
+                if paused {
+                    let _ = app_stat_tx.send(VORAppIdentifier {
+                        index: id,
+                        status: VORAppStatus::Paused,
+                    });
+                }
+            }
+            Some(RouterEvent::Msg(RouterMsg::StartCapture(path))) => recorder.start(&path),
+            Some(RouterEvent::Msg(RouterMsg::StopCapture)) => recorder.stop(),
+            Some(RouterEvent::Msg(RouterMsg::ShutdownAll)) => {
                 // Send shutdown to all threads
 
-                // Shutdown osc parse thread first
-                osc_parse_tx.send(true).unwrap();
+                // Shutdown osc parse threads first
+                for osc_parse_tx in &osc_parse_txs {
+                    let _ = osc_parse_tx.send(true);
+                }
+                // Write out the capture before the GUI or the process goes away
+                recorder.stop();
 
                 //drop(vrc_sock);
                 //println!("[*] Shutdown signal: OSC receive thread");
+                queues.close_all();
 
                 // Shutdown app route threads
-                for app_route_thread_channel in artc {
-                    let _ = app_route_thread_channel.send(true);
+                for handle in routes.values() {
+                    let _ = handle.router_tx.send(true);
                 }
                 //println!("[*] Shutdown signal: Route threads");
 
-                if async_mode {
-                    //println!("[*] Async runtime background shutdown.");
-
-                    for h in async_threads {
-                        h.abort();
-                        async_rt.as_ref().unwrap().spawn(async {
-                            let _ = h.await;
-                        });
-                    }
-                    async_rt.unwrap().shutdown_background();
+                if let Some(rt) = async_rt {
+                    // Async routes stop like sync route threads and send their final status and counters
+                    let tasks: Vec<_> = routes.into_values().filter_map(|h| h.task).collect();
+                    rt.block_on(async {
+                        for task in tasks {
+                            stop_task(task).await;
+                        }
+                    });
+                    rt.shutdown_background();
                 }
 
                 // Shutdown router thread last
                 //println!("[*] Shutdown signal: Router thread");
                 return; // Shutdown router thread.
-            } //_ =>{},
+            }
+        }
+
+        // Restart failed routes whose backoff ended
+        for (id, app) in supervisor.due() {
+            routes.insert(id, route_ctx.spawn_route(async_rt.as_ref(), app, id));
         }
     }
 }
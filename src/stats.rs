@@ -0,0 +1,168 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// How often routes and listeners report their traffic counters
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Upper bounds of the routing latency buckets in seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+// Traffic counters of an app route
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RouteStats {
+    // Packets received from the listeners
+    pub received: u64,
+    // Dropped by the route packet filter
+    pub filtered: u64,
+    // Dropped by "Skip unchanged values"
+    pub unchanged: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub send_errors: u64,
+    // Packets the route's queue dropped or coalesced because the app fell behind (Queue policy)
+    pub queue_dropped: u64,
+    pub queue_coalesced: u64,
+    pub last_send: Option<Instant>,
+    // Time from the listener receiving a packet to the route sending it
+    pub latency: LatencyHistogram,
+}
+
+impl RouteStats {
+    pub fn sent(&mut self, bytes: usize) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+        self.last_send = Some(Instant::now());
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyHistogram {
+    // Observations per bucket (not cumulative). The last bucket is +Inf.
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|le| secs <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+impl fmt::Display for RouteStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Sent: {} ({}) Errors: {} Queue dropped: {} Coalesced: {}",
+            self.packets,
+            format_bytes(self.bytes),
+            self.send_errors,
+            self.queue_dropped,
+            self.queue_coalesced
+        )?;
+        match self.last_send {
+            Some(t) => write!(f, " Last: {:.1}s ago", t.elapsed().as_secs_f32()),
+            None => write!(f, " Last: never"),
+        }
+    }
+}
+
+// Totals of a router listener
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ListenerStats {
+    pub received: u64,
+    pub allowed: u64,
+    pub dropped: u64,
+    // Packets that couldn't be parsed as OSC (counted as allowed or dropped too)
+    pub malformed: u64,
+    // Dropped because they couldn't be parsed (included in dropped)
+    pub dropped_malformed: u64,
+}
+
+impl ListenerStats {
+    pub fn count(&mut self, allowed: bool, malformed: bool) {
+        self.received += 1;
+        if allowed {
+            self.allowed += 1;
+        } else {
+            self.dropped += 1;
+        }
+        if malformed {
+            self.malformed += 1;
+            if !allowed {
+                self.dropped_malformed += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Display for ListenerStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Received: {} Allowed: {} Dropped: {} Malformed: {}",
+            self.received, self.allowed, self.dropped, self.malformed
+        )
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024. * 1024.)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.),
+        b => format!("{} B", b),
+    }
+}
+
+// Counters reported at most once per interval and only when they changed
+pub struct StatsReporter<T> {
+    pub stats: T,
+    reported: T,
+    last_report: Instant,
+}
+
+impl<T: Copy + Default + PartialEq> StatsReporter<T> {
+    pub fn new() -> Self {
+        Self {
+            stats: T::default(),
+            reported: T::default(),
+            last_report: Instant::now(),
+        }
+    }
+
+    // Counters to report if they changed and the interval passed
+    pub fn take_update(&mut self) -> Option<T> {
+        if self.last_report.elapsed() < STATS_REPORT_INTERVAL {
+            return None;
+        }
+        self.flush()
+    }
+
+    // Counters to report if they changed
+    pub fn flush(&mut self) -> Option<T> {
+        if self.stats == self.reported {
+            return None;
+        }
+        self.reported = self.stats;
+        self.last_report = Instant::now();
+        Some(self.stats)
+    }
+
+    // When the counters are due to be reported
+    pub fn next_report(&self) -> Option<Instant> {
+        if self.stats == self.reported {
+            None
+        } else {
+            Some(self.last_report + STATS_REPORT_INTERVAL)
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::config::VORConfig;
+
+// Delay before the first restart of a failed route. It doubles with every failure up to the max.
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+// A route that ran this long before failing starts over at the minimum delay
+const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(60);
+
+// Restart counters of a route
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RestartStats {
+    pub restarts: u64,
+    pub last_error: String,
+    // Delay until the pending restart
+    pub backoff: Duration,
+}
+
+impl fmt::Display for RestartStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Restarts: {} Last error: {}", self.restarts, self.last_error)
+    }
+}
+
+struct SupervisedRoute {
+    stats: RestartStats,
+    // Delay of the next failure's restart
+    next_backoff: Duration,
+    // Config the route restarts with and when
+    pending: Option<(VORConfig, Instant)>,
+}
+
+/*
+    Restart bookkeeping for routes that failed on errors that can go away (Bind, resolve or repeated send failures).
+    The router restarts due routes, this only decides when.
+*/
+#[derive(Default)]
+pub struct RouteSupervisor {
+    routes: HashMap<i64, SupervisedRoute>,
+}
+
+impl RouteSupervisor {
+    // Schedule the restart of a route that failed after running for ran_for
+    pub fn failed(&mut self, id: i64, app: VORConfig, error: String, ran_for: Duration) -> RestartStats {
+        let route = self.routes.entry(id).or_insert_with(|| SupervisedRoute {
+            stats: RestartStats::default(),
+            next_backoff: RESTART_BACKOFF_MIN,
+            pending: None,
+        });
+        if ran_for >= RESTART_BACKOFF_RESET {
+            route.next_backoff = RESTART_BACKOFF_MIN;
+        }
+        let backoff = route.next_backoff;
+        route.next_backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+
+        route.stats.restarts += 1;
+        route.stats.last_error = error;
+        route.stats.backoff = backoff;
+        route.pending = Some((app, Instant::now() + backoff));
+        route.stats.clone()
+    }
+
+    // The user started, updated or paused the route. Returns true if a restart was pending.
+    pub fn cancel(&mut self, id: i64) -> bool {
+        match self.routes.get_mut(&id) {
+            Some(route) => {
+                route.next_backoff = RESTART_BACKOFF_MIN;
+                route.pending.take().is_some()
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: i64) {
+        self.routes.remove(&id);
+    }
+
+    pub fn next_restart(&self) -> Option<Instant> {
+        self.routes
+            .values()
+            .filter_map(|r| r.pending.as_ref().map(|p| p.1))
+            .min()
+    }
+
+    // Routes whose restart is due
+    pub fn due(&mut self) -> Vec<(i64, VORConfig)> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (id, route) in self.routes.iter_mut() {
+            if route.pending.as_ref().is_some_and(|p| p.1 <= now) {
+                if let Some((app, _)) = route.pending.take() {
+                    due.push((*id, app));
+                }
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(supervisor: &mut RouteSupervisor, ran_for: Duration) -> RestartStats {
+        supervisor.failed(1, VORConfig::default(), "Failed to bind app UdpSocket".to_string(), ran_for)
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut supervisor = RouteSupervisor::default();
+        let backoffs: Vec<u64> = (0..8)
+            .map(|_| fail(&mut supervisor, Duration::ZERO).backoff.as_secs())
+            .collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(fail(&mut supervisor, Duration::ZERO).restarts, 9);
+
+        // A route that ran long enough starts over
+        assert_eq!(fail(&mut supervisor, RESTART_BACKOFF_RESET).backoff, RESTART_BACKOFF_MIN);
+    }
+
+    #[test]
+    fn cancel_clears_pending_restart() {
+        let mut supervisor = RouteSupervisor::default();
+        assert_eq!(supervisor.next_restart(), None);
+
+        fail(&mut supervisor, Duration::ZERO);
+        assert!(supervisor.next_restart().is_some());
+        assert!(supervisor.due().is_empty());
+
+        assert!(supervisor.cancel(1));
+        assert!(!supervisor.cancel(1));
+        assert_eq!(supervisor.next_restart(), None);
+
+        // Cancelling resets the backoff
+        assert_eq!(fail(&mut supervisor, Duration::ZERO).backoff, RESTART_BACKOFF_MIN);
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{VORAppIdentifier, VORAppStatus};
+use crate::packet::SharedOscBuf;
+use crate::routing::{send_app_reply, AppReply};
+use crate::vorerr::app_error;
+use crate::websocket::WsSink;
+
+// SLIP special bytes (RFC 1055)
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const RECONNECT_MIN: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Frames kept while disconnected. Oldest frames are dropped first.
+const MAX_BUFFERED_FRAMES: usize = 4096;
+
+// How a route sends to the app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum Transport {
+    #[default]
+    Udp,
+    // SLIP framed OSC 1.1 stream
+    Tcp,
+    // Local WebSocket server on the app host/port for browser clients
+    WebSocket,
+    // SOCK_DGRAM Unix socket at the app's unix_path (Linux)
+    UnixDgram,
+}
+
+// Sink used instead of the route's UDP socket
+pub enum RouteSink {
+    Tcp(TcpSink),
+    WebSocket(WsSink),
+    UnixDgram(UnixSink),
+}
+
+impl RouteSink {
+    // Stream sinks queue the packet so only Unix sockets can fail here
+    pub fn send(&self, buf: &SharedOscBuf) -> std::io::Result<()> {
+        match self {
+            RouteSink::Tcp(tcp) => {
+                tcp.send(&buf.bytes);
+                Ok(())
+            }
+            RouteSink::WebSocket(ws) => {
+                ws.send(buf);
+                Ok(())
+            }
+            RouteSink::UnixDgram(unix) => unix.send(&buf.bytes),
+        }
+    }
+
+    // TCP and WebSocket sinks report the route's connection status themselves
+    pub fn reports_status(&self) -> bool {
+        !matches!(self, RouteSink::UnixDgram(_))
+    }
+}
+
+/*
+    Route status sender for a sink's thread.
+    Closed when the route drops the sink, the thread may still be winding down (connect or write timeout)
+    and must not report a status after the route's last status.
+*/
+#[derive(Clone)]
+pub struct SinkStatus {
+    ai: i64,
+    app_stat_tx: Sender<VORAppIdentifier>,
+    open: Arc<Mutex<bool>>,
+}
+
+impl SinkStatus {
+    pub fn new(ai: i64, app_stat_tx: Sender<VORAppIdentifier>) -> Self {
+        Self {
+            ai,
+            app_stat_tx,
+            open: Arc::new(Mutex::new(true)),
+        }
+    }
+
+    pub fn send(&self, status: VORAppStatus) {
+        // Held while sending so close() can't return in the middle of a send
+        let open = self.open.lock().unwrap();
+        if *open {
+            let _ = self.app_stat_tx.send(VORAppIdentifier {
+                index: self.ai,
+                status,
+            });
+        }
+    }
+
+    pub fn error(&self, err_id: i32, msg: String) {
+        self.send(app_error(self.ai, err_id, msg).status);
+    }
+
+    pub fn close(&self) {
+        *self.open.lock().unwrap() = false;
+    }
+}
+
+/*
+    Unbound Unix datagram socket sending to a socket file.
+    Non-blocking so the async route never waits on a full receive queue.
+*/
+pub struct UnixSink {
+    #[cfg(unix)]
+    sock: std::os::unix::net::UnixDatagram,
+    path: String,
+}
+
+impl UnixSink {
+    #[cfg(unix)]
+    pub fn new(path: String) -> std::io::Result<Self> {
+        let sock = std::os::unix::net::UnixDatagram::unbound()?;
+        sock.set_nonblocking(true)?;
+        Ok(Self { sock, path })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_path: String) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Unix sockets are not supported on this OS",
+        ))
+    }
+
+    #[cfg(unix)]
+    fn send(&self, buf: &[u8]) -> std::io::Result<()> {
+        self.sock.send_to(buf, &self.path).map(|_bs| ())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _buf: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+// Double-ended SLIP frame as described by OSC 1.1
+pub fn slip_encode(buf: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(buf.len() + 2);
+    frame.push(SLIP_END);
+    for &b in buf {
+        match b {
+            SLIP_END => frame.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => frame.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            _ => frame.push(b),
+        }
+    }
+    frame.push(SLIP_END);
+    frame
+}
+
+#[derive(Default)]
+pub struct SlipDecoder {
+    frame: Vec<u8>,
+    escaped: bool,
+}
+
+impl SlipDecoder {
+    // Feed stream bytes and get back every frame they completed
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for &b in data {
+            if self.escaped {
+                self.escaped = false;
+                match b {
+                    SLIP_ESC_END => self.frame.push(SLIP_END),
+                    SLIP_ESC_ESC => self.frame.push(SLIP_ESC),
+                    // Protocol violation. Keep the byte like most SLIP implementations.
+                    _ => self.frame.push(b),
+                }
+                continue;
+            }
+
+            match b {
+                SLIP_END => {
+                    // Empty frames come from the double END between frames
+                    if !self.frame.is_empty() {
+                        frames.push(std::mem::take(&mut self.frame));
+                    }
+                }
+                SLIP_ESC => self.escaped = true,
+                _ => self.frame.push(b),
+            }
+        }
+        frames
+    }
+}
+
+/*
+    TCP connection to an app.
+    Frames are handed to a connection thread that reconnects with backoff
+    and buffers frames while the app is not connected.
+    The connection thread owns the route's Running/Connecting/Disconnected status.
+*/
+pub struct TcpSink {
+    frame_tx: Sender<Vec<u8>>,
+    stop: Arc<AtomicBool>,
+    status: SinkStatus,
+}
+
+impl TcpSink {
+    pub fn new(
+        ai: i64,
+        app_name: String,
+        target: SocketAddr,
+        app_stat_tx: Sender<VORAppIdentifier>,
+        reply_tx: Option<Sender<AppReply>>,
+    ) -> Self {
+        let (frame_tx, frame_rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let status = SinkStatus::new(ai, app_stat_tx);
+        let conn = TcpConnection {
+            app_name,
+            target,
+            status: status.clone(),
+            reply_tx,
+            stop: stop.clone(),
+            stream: None,
+            closed: Arc::new(AtomicBool::new(false)),
+            pending: VecDeque::new(),
+            connected: None,
+        };
+
+        thread::spawn(move || conn.run(frame_rx));
+        Self { frame_tx, stop, status }
+    }
+
+    pub fn send(&self, buf: &[u8]) {
+        let _ = self.frame_tx.send(slip_encode(buf));
+    }
+}
+
+impl Drop for TcpSink {
+    // Dropped from async route tasks, so the connection thread is left to end on its own instead of joined
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.status.close();
+    }
+}
+
+struct TcpConnection {
+    app_name: String,
+    target: SocketAddr,
+    status: SinkStatus,
+    reply_tx: Option<Sender<AppReply>>,
+    stop: Arc<AtomicBool>,
+    stream: Option<TcpStream>,
+    // Set by the reader thread when the app closes the connection
+    closed: Arc<AtomicBool>,
+    pending: VecDeque<Vec<u8>>,
+    // Last reported connection state
+    connected: Option<bool>,
+}
+
+impl TcpConnection {
+    fn run(mut self, frame_rx: mpsc::Receiver<Vec<u8>>) {
+        let mut next_connect = Instant::now();
+        let mut backoff = RECONNECT_MIN;
+        self.send_status(VORAppStatus::Connecting);
+
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if self.stream.is_some() && self.closed.load(Ordering::Relaxed) {
+                self.disconnect("Connection closed by app".to_string());
+                next_connect = Instant::now();
+            }
+
+            if self.stream.is_none() && Instant::now() >= next_connect {
+                match self.connect() {
+                    Ok(()) => backoff = RECONNECT_MIN,
+                    Err(e) => {
+                        if self.connected != Some(false) {
+                            self.connected = Some(false);
+                            self.send_status(VORAppStatus::Disconnected(e.to_string()));
+                        }
+                        next_connect = Instant::now() + backoff;
+                        backoff = (backoff * 2).min(RECONNECT_MAX);
+                    }
+                }
+            }
+
+            match frame_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(frame) => self.buffer(frame),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            while let Ok(frame) = frame_rx.try_recv() {
+                self.buffer(frame);
+            }
+
+            if let Err(e) = self.flush() {
+                self.disconnect(e.to_string());
+                next_connect = Instant::now();
+            }
+        }
+
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn send_status(&self, status: VORAppStatus) {
+        self.status.send(status);
+    }
+
+    fn connect(&mut self) -> std::io::Result<()> {
+        let stream = TcpStream::connect_timeout(&self.target, CONNECT_TIMEOUT)?;
+        let _ = stream.set_nodelay(true);
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        // Reader thread ends when the connection is shut down
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader = stream.try_clone()?;
+        let reader_closed = closed.clone();
+        let reply_tx = self.reply_tx.clone();
+        let (app_name, target) = (self.app_name.clone(), self.target);
+        thread::spawn(move || {
+            read_app_replies(reader, reply_tx, app_name, target);
+            reader_closed.store(true, Ordering::Relaxed);
+        });
+
+        self.closed = closed;
+        self.stream = Some(stream);
+        self.connected = Some(true);
+        self.send_status(VORAppStatus::Running);
+        Ok(())
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.connected = Some(false);
+        self.send_status(VORAppStatus::Disconnected(reason));
+    }
+
+    fn buffer(&mut self, frame: Vec<u8>) {
+        if self.pending.len() >= MAX_BUFFERED_FRAMES {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(frame);
+    }
+
+    // Write buffered frames. A frame stays buffered until it was fully written.
+    fn flush(&mut self) -> std::io::Result<()> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        while let Some(frame) = self.pending.front() {
+            stream.write_all(frame)?;
+            self.pending.pop_front();
+        }
+        Ok(())
+    }
+}
+
+// Decode SLIP frames sent by the app. Replies are only forwarded if the route returns them to VRChat.
+fn read_app_replies(
+    mut stream: TcpStream,
+    reply_tx: Option<Sender<AppReply>>,
+    app_name: String,
+    target: SocketAddr,
+) {
+    let mut decoder = SlipDecoder::default();
+    let mut buf = [0u8; 4096];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(br) => {
+                for frame in decoder.feed(&buf[..br]) {
+                    if let Some(ref reply_tx) = reply_tx {
+                        send_app_reply(reply_tx, &app_name, target, &frame);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slip_round_trip_with_escapes() {
+        let payload = vec![0x01, SLIP_END, 0x02, SLIP_ESC, SLIP_ESC_END, 0x03];
+        let frame = slip_encode(&payload);
+
+        assert_eq!(frame.first(), Some(&SLIP_END));
+        assert_eq!(frame.last(), Some(&SLIP_END));
+        assert_eq!(SlipDecoder::default().feed(&frame), vec![payload]);
+    }
+
+    #[test]
+    fn slip_decoder_handles_split_frames() {
+        let mut stream = slip_encode(b"/a");
+        stream.extend(slip_encode(&[SLIP_ESC, 0x04]));
+
+        let mut decoder = SlipDecoder::default();
+        let (first, second) = stream.split_at(5);
+        let mut frames = decoder.feed(first);
+        frames.extend(decoder.feed(second));
+
+        assert_eq!(frames, vec![b"/a".to_vec(), vec![SLIP_ESC, 0x04]]);
+    }
+
+    #[test]
+    fn dropped_tcp_sink_reports_nothing() {
+        // Nothing listens here, the connection thread keeps reconnecting
+        let target = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (app_stat_tx, app_stat_rx) = mpsc::channel();
+        let sink = TcpSink::new(0, "App".to_string(), target, app_stat_tx, None);
+        sink.send(b"/a");
+
+        let dropping = Instant::now();
+        drop(sink);
+        assert!(dropping.elapsed() < POLL_INTERVAL);
+        while app_stat_rx.try_recv().is_ok() {}
+
+        thread::sleep(RECONNECT_MIN * 2);
+        assert!(app_stat_rx.try_recv().is_err());
+    }
+}
@@ -1,18 +1,29 @@
 #[cfg(target_os = "linux")]
 use crate::vorutils::get_user_home_dir;
 use crate::config::vor_root;
-use crate::pf::PacketFilter;
-use crate::routedbg::DebugPacket;
+use crate::pf::{BundleMode, PacketFilter};
+use crate::routedbg::{DebugPacket, OutgoingDebugMode};
 use crate::VCArgs;
 use crate::{
     config::{
-        AppConfigCheck, AppConfigState, AppConflicts, InputValidation, RouterConfig,
+        AppConfigCheck, AppConfigState, AppConflicts, InputValidation, ListenerConfig, RouterConfig,
         VORAppIdentifier, VORAppStatus, VORConfig, VORConfigWrapper,
     },
+    ratelimit::{RateLimit, RateLimitStats},
+    stats::{ListenerStats, RouteStats},
+    supervisor::RestartStats,
+    capture::CaptureStatus,
+    dedup::Dedup,
+    discovery::{DiscoveredApp, Discovery},
+    transport::Transport,
+    queue::QueuePolicy,
+    replay::{ReplayHandle, ReplayOptions, ReplayTarget},
+    websocket::WsFormat,
+    rewrite::{AddressRewriter, RewriteRules},
     routedbg,
-    routing::{route_main, RouterMsg},
+    routing::{route_main, RouteEvent, RouterMsg, RouterOptions},
     vorupdate::{VORUpdater, VERSION},
-    vorutils::{check_valid_ipv4, check_valid_port, file_exists},
+    vorutils::{check_valid_host, check_valid_port, check_valid_ttl, file_exists, host_port},
 };
 
 use eframe::egui::{
@@ -25,6 +36,7 @@ use eframe::{
     App,
 };
 use rosc::OscPacket;
+use std::collections::HashMap;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::{fs, thread};
 
@@ -39,11 +51,73 @@ pub struct VORGUI {
     new_app: Option<VORConfigWrapper>,
     new_app_cf_exists_err: AppConfigCheck,
     router_msg_recvr: Option<Receiver<VORAppIdentifier>>,
+    route_event_recvr: Option<Receiver<RouteEvent>>,
     pf: PacketFilter,
+    vrc_pf: PacketFilter,
+    pf_target: PFTarget,
     pf_wl_new: (String, bool),
     pf_bl_new: (String, bool),
     update_engine: VORUpdater,
     route_debug: Option<routedbg::VORDebug>,
+    next_route_id: i64,
+    rate_limit_stats: HashMap<i64, RateLimitStats>,
+    route_stats: HashMap<i64, RouteStats>,
+    // Warnings shown next to the app's status (Queue over its high-water mark)
+    route_warnings: HashMap<i64, String>,
+    // Restart counters shown in the Apps tab
+    route_restarts: HashMap<i64, RestartStats>,
+    // Listener totals in the order they were first reported
+    listener_stats: Vec<(String, ListenerStats)>,
+    capture_path: String,
+    capture_status: Option<CaptureStatus>,
+    // Last failed send of an app reply to VRChat
+    return_error: Option<String>,
+    replay: ReplayForm,
+    replay_handle: Option<ReplayHandle>,
+    // Packets sent by the last replay or its error
+    replay_result: Option<Result<u64, String>>,
+    discovery: Option<Discovery>,
+    discovered_apps: Vec<DiscoveredApp>,
+    discovery_whitelist: bool,
+    // Failed scan or why a discovered app couldn't be added
+    discovery_err: Option<String>,
+}
+
+// Replay settings in the debug window
+struct ReplayForm {
+    path: String,
+    // None replays into the router listeners, otherwise straight to the app with this route id
+    route: Option<i64>,
+    speed: String,
+    looped: bool,
+    // Seconds from the first packet
+    from: String,
+    to: String,
+    // Comma separated OSC addresses
+    addresses: String,
+}
+
+impl ReplayForm {
+    fn options(&self) -> Result<ReplayOptions, String> {
+        let speed = self
+            .speed
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid replay speed: {}", self.speed))?;
+        let seconds = |s: &str| -> Result<Option<f64>, String> {
+            if s.trim().is_empty() {
+                return Ok(None);
+            }
+            s.trim().parse::<f64>().map(Some).map_err(|_| format!("Invalid replay time: {}", s))
+        };
+        let addresses = self
+            .addresses
+            .split(',')
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect();
+        ReplayOptions::new(speed, self.looped, seconds(&self.from)?, seconds(&self.to)?, addresses)
+    }
 }
 
 enum VORExecutionState {
@@ -52,6 +126,14 @@ enum VORExecutionState {
     Error(String),
 }
 
+// Which packet filter is being edited
+#[derive(Clone, Copy, PartialEq)]
+pub enum PFTarget {
+    Incoming,
+    Return,
+    Route(usize),
+}
+
 pub enum VORGUITab {
     Main,
     Apps,
@@ -66,7 +148,9 @@ impl VORGUI {
         configs: Vec<(VORConfigWrapper, VORAppStatus, AppConfigState)>,
         vor_router_config: RouterConfig,
         pf: PacketFilter,
+        vrc_pf: PacketFilter,
     ) -> Self {
+        let next_route_id = configs.len() as i64;
         let mut app_obj = VORGUI {
             configs,
             vc_args,
@@ -78,11 +162,38 @@ impl VORGUI {
             new_app: None,
             new_app_cf_exists_err: AppConfigCheck::SUCCESS,
             router_msg_recvr: None,
+            route_event_recvr: None,
             pf,
+            vrc_pf,
+            pf_target: PFTarget::Incoming,
             pf_bl_new: (String::new(), false),
             pf_wl_new: (String::new(), false),
             update_engine: VORUpdater::new(),
             route_debug: None,
+            next_route_id,
+            rate_limit_stats: HashMap::new(),
+            route_warnings: HashMap::new(),
+            route_restarts: HashMap::new(),
+            route_stats: HashMap::new(),
+            listener_stats: Vec::new(),
+            capture_path: String::from("vor_capture.vorcap"),
+            capture_status: None,
+            return_error: None,
+            replay: ReplayForm {
+                path: String::from("vor_capture.vorcap"),
+                route: None,
+                speed: String::from("1"),
+                looped: false,
+                from: String::new(),
+                to: String::new(),
+                addresses: String::new(),
+            },
+            replay_handle: None,
+            replay_result: None,
+            discovery: None,
+            discovered_apps: Vec::new(),
+            discovery_whitelist: true,
+            discovery_err: None,
         };
 
         // Read config values
@@ -93,6 +204,12 @@ impl VORGUI {
         let visuals = Visuals::dark();
         cc.egui_ctx.set_visuals(visuals);
 
+        // Capture flag, the router records to this file whenever it starts
+        if let Some(ref path) = app_obj.vc_args.capture {
+            app_obj.capture_path = path.clone();
+            app_obj.replay.path = path.clone();
+        }
+
         // Enable on start flag
         if app_obj.vc_args.enable_on_start {
             app_obj.start_router();
@@ -185,6 +302,10 @@ impl VORGUI {
                         &mut self.route_debug.as_mut().unwrap().ui_opts.show_outgoing,
                         "OUTGOING",
                     );
+                    ui.checkbox(
+                        &mut self.route_debug.as_mut().unwrap().ui_opts.show_returning,
+                        "RETURNING",
+                    );
                     ui.checkbox(
                         &mut self.route_debug.as_mut().unwrap().ui_opts.show_allowed,
                         "ALLOWED",
@@ -205,6 +326,11 @@ impl VORGUI {
                         &mut self.route_debug.as_mut().unwrap().ui_opts.search_query,
                     );
                 });
+                egui::CollapsingHeader::new("Replay capture")
+                    .id_source("replay_section")
+                    .show(ui, |ui| {
+                        self.replay_controls(ui);
+                    });
 
                 ui.separator();
                 ScrollArea::new([true, true])
@@ -240,8 +366,8 @@ impl VORGUI {
                                             {
                                                 egui::CollapsingHeader::new(
                                                     RichText::new(format!(
-                                                        "Incoming({:?}): {}",
-                                                        pkt.mode, pkt.from_address
+                                                        "Incoming({:?}): {} ({})",
+                                                        pkt.mode, pkt.listener, pkt.from_address
                                                     ))
                                                     .color(Color32::GREEN),
                                                 )
@@ -253,6 +379,13 @@ impl VORGUI {
                                                         "L3 Src Address: {}",
                                                         pkt.from_address
                                                     ));
+                                                    ui.horizontal_wrapped(|ui| {
+                                                        ui.label(RichText::new("Listener:"));
+                                                        ui.colored_label(
+                                                            Color32::LIGHT_BLUE,
+                                                            RichText::new(&pkt.listener),
+                                                        );
+                                                    });
                                                     ui.horizontal_wrapped(|ui| {
                                                         ui.label(RichText::new("PF Decision:"));
                                                         ui.colored_label(
@@ -311,8 +444,8 @@ impl VORGUI {
                                             {
                                                 egui::CollapsingHeader::new(
                                                     RichText::new(format!(
-                                                        "Incoming({:?}): {}",
-                                                        pkt.mode, pkt.from_address
+                                                        "Incoming({:?}): {} ({})",
+                                                        pkt.mode, pkt.listener, pkt.from_address
                                                     ))
                                                     .color(Color32::RED),
                                                 )
@@ -324,6 +457,13 @@ impl VORGUI {
                                                         "L3 Src Address: {}",
                                                         pkt.from_address
                                                     ));
+                                                    ui.horizontal_wrapped(|ui| {
+                                                        ui.label(RichText::new("Listener:"));
+                                                        ui.colored_label(
+                                                            Color32::LIGHT_BLUE,
+                                                            RichText::new(&pkt.listener),
+                                                        );
+                                                    });
                                                     ui.horizontal_wrapped(|ui| {
                                                         ui.label(RichText::new("PF Decision:"));
                                                         ui.colored_label(
@@ -361,7 +501,10 @@ impl VORGUI {
                                     }
                                 }
                                 DebugPacket::OUTGOING(pkt) => {
-                                    if self.route_debug.as_ref().unwrap().ui_opts.show_outgoing {
+                                    if self.route_debug.as_ref().unwrap().ui_opts.show_outgoing
+                                        && (!pkt.mode.is_dropped()
+                                            || self.route_debug.as_ref().unwrap().ui_opts.show_dropped)
+                                    {
                                         if packet.search(
                                             self.route_debug
                                                 .as_ref()
@@ -377,11 +520,18 @@ impl VORGUI {
                                             .search_query
                                             .is_empty()
                                         {
-                                            egui::CollapsingHeader::new(
-                                                RichText::new(format!(
+                                            let header = match &pkt.mode {
+                                                OutgoingDebugMode::DROPPED(reason) => format!(
+                                                    "Outgoing(DROPPED: {}): {} ({})",
+                                                    reason, pkt.route, pkt.to_address
+                                                ),
+                                                _ => format!(
                                                     "Outgoing: {} ({})",
                                                     pkt.route, pkt.to_address
-                                                ))
+                                                ),
+                                            };
+                                            egui::CollapsingHeader::new(
+                                                RichText::new(header)
                                                 .color(Color32::from_rgb(0xef, 0x98, 0xff)),
                                             )
                                             .id_source(id_increment)
@@ -400,6 +550,33 @@ impl VORGUI {
                                                             RichText::new(format!("{}", pkt.route)),
                                                         );
                                                     });
+                                                    if let OutgoingDebugMode::DROPPED(reason) = &pkt.mode {
+                                                        ui.horizontal_wrapped(|ui| {
+                                                            ui.label(RichText::new("Dropped:"));
+                                                            ui.colored_label(Color32::RED, RichText::new(reason));
+                                                        });
+                                                    }
+
+                                                    // Address rewrite: original -> rewritten
+                                                    if let (Some(OscPacket::Message(orig)), Some(OscPacket::Message(msg))) =
+                                                        (&pkt.original_osc_packet, &pkt.osc_packet)
+                                                    {
+                                                        ui.horizontal_wrapped(|ui| {
+                                                            ui.label(RichText::new("Rewritten:"));
+                                                            ui.colored_label(
+                                                                Color32::GOLD,
+                                                                RichText::new(format!("{} -> {}", orig.addr, msg.addr)),
+                                                            );
+                                                        });
+                                                    }
+                                                    if let Some(orig) = &pkt.original_osc_packet {
+                                                        egui::CollapsingHeader::new(RichText::new(
+                                                            "Original OSC Packet",
+                                                        ))
+                                                        .show(ui, |ui| {
+                                                            ui.label(RichText::new(format!("{:#?}", orig)));
+                                                        });
+                                                    }
 
                                                     if ui.button("Copy OSC Address").clicked() {
                                                         ui.output().copied_text =
@@ -427,12 +604,149 @@ impl VORGUI {
                                         }
                                     }
                                 }
+                                DebugPacket::RETURNING(pkt) => {
+                                    let ui_opts = &self.route_debug.as_ref().unwrap().ui_opts;
+                                    if ui_opts.show_returning
+                                        && ((ui_opts.show_allowed && pkt.mode.is_allowed())
+                                            || (ui_opts.show_dropped && pkt.mode.is_dropped()))
+                                        && (ui_opts.search_query.is_empty()
+                                            || packet.search(ui_opts.search_query.clone()))
+                                    {
+                                        let mode_color = if pkt.mode.is_allowed() {
+                                            Color32::GREEN
+                                        } else {
+                                            Color32::RED
+                                        };
+                                        egui::CollapsingHeader::new(
+                                            RichText::new(format!(
+                                                "Returning({:?}): {} ({})",
+                                                pkt.mode, pkt.route, pkt.from_address
+                                            ))
+                                            .color(Color32::from_rgb(0x98, 0xd8, 0xff)),
+                                        )
+                                        .id_source(id_increment)
+                                        .show(ui, |ui| {
+                                            ui.label(format!(
+                                                "L3 Src Address: {}",
+                                                pkt.from_address
+                                            ));
+                                            ui.horizontal_wrapped(|ui| {
+                                                ui.label(RichText::new("Route:"));
+                                                ui.colored_label(
+                                                    Color32::GREEN,
+                                                    RichText::new(&pkt.route),
+                                                );
+                                            });
+                                            ui.horizontal_wrapped(|ui| {
+                                                ui.label(RichText::new("PF Decision:"));
+                                                ui.colored_label(
+                                                    mode_color,
+                                                    RichText::new(format!("{:?}", pkt.mode)),
+                                                );
+                                            });
+                                            ui.label(format!(
+                                                "Buffer length: {}",
+                                                pkt.packet_buffer.len()
+                                            ));
+
+                                            if let Some(OscPacket::Message(msg)) = &pkt.osc_packet {
+                                                if ui.button("Copy OSC Address").clicked() {
+                                                    ui.output().copied_text = msg.addr.clone();
+                                                }
+                                            }
+                                            egui::CollapsingHeader::new(RichText::new(
+                                                "OSC Packet",
+                                            ))
+                                            .show(ui, |ui| {
+                                                ui.label(RichText::new(format!(
+                                                    "{:#?}",
+                                                    pkt.osc_packet
+                                                )));
+                                            });
+                                        });
+                                        id_increment += 1;
+                                    }
+                                }
                             }
                         }
                     });
             });
     }
 
+    // Feed a capture file back into the router's listeners or straight to an app
+    fn replay_controls(&mut self, ui: &mut egui::Ui) {
+        if let Some(result) = self.replay_handle.as_mut().and_then(|h| h.finished()) {
+            self.replay_handle = None;
+            self.replay_result = Some(result);
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Capture file: ");
+            ui.text_edit_singleline(&mut self.replay.path);
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Send to: ");
+            ui.selectable_value(&mut self.replay.route, None, "Router listeners");
+            for (app, _, _) in self.configs.iter() {
+                if app.config_data.transport == Transport::Udp {
+                    ui.selectable_value(&mut self.replay.route, Some(app.route_id), &app.config_data.app_name);
+                }
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Speed: ");
+            ui.add(egui::TextEdit::singleline(&mut self.replay.speed).desired_width(40.));
+            ui.checkbox(&mut self.replay.looped, "Loop");
+            ui.label("From (s): ");
+            ui.add(egui::TextEdit::singleline(&mut self.replay.from).desired_width(40.));
+            ui.label("To (s): ");
+            ui.add(egui::TextEdit::singleline(&mut self.replay.to).desired_width(40.));
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Addresses: ");
+            ui.text_edit_singleline(&mut self.replay.addresses).on_hover_text("Comma separated, all if empty");
+        });
+        ui.horizontal_wrapped(|ui| {
+            if self.replay_handle.is_some() {
+                if ui.button("Stop replay").clicked() {
+                    self.replay_result = self.replay_handle.take().and_then(|mut h| h.stop());
+                }
+                ui.label(RichText::new("Replaying..").color(Color32::LIGHT_BLUE));
+                return;
+            }
+            if ui.button("Replay").clicked() {
+                self.replay_result = match self.start_replay() {
+                    Ok(handle) => {
+                        self.replay_handle = Some(handle);
+                        None
+                    }
+                    Err(e) => Some(Err(e)),
+                };
+            }
+            match self.replay_result {
+                Some(Ok(sent)) => {
+                    ui.label(RichText::new(format!("Replayed {} packets", sent)).color(Color32::LIGHT_BLUE));
+                }
+                Some(Err(ref e)) => {
+                    ui.label(RichText::new(e).color(Color32::GOLD));
+                }
+                None => {}
+            }
+        });
+    }
+
+    fn start_replay(&self) -> Result<ReplayHandle, String> {
+        let opts = self.replay.options()?;
+        let target = match self.replay.route {
+            Some(id) => match self.configs.iter().find(|c| c.0.route_id == id) {
+                Some(app) => ReplayTarget::app(&app.0.config_data)?,
+                None => return Err("App no longer exists".to_string()),
+            },
+            None => ReplayTarget::listeners(&self.vor_router_config)?,
+        };
+        Ok(ReplayHandle::start(self.replay.path.clone(), target, opts))
+    }
+
     fn debug_status_refresh(&mut self) {
         // Update VOR Debug structure
         if self.route_debug.is_some() {
@@ -461,37 +775,149 @@ impl VORGUI {
     }
 
     fn status_refresh(&mut self) {
-        let status = match self.router_msg_recvr.as_ref() {
-            Some(recvr) => match recvr.try_recv() {
-                Ok(status) => status,
-                Err(_e) => {
-                    return;
-                }
-            },
-            None => return,
-        };
-        if status.index == -1 {
-            println!("[!] VOR failed to bind listener socket.. Not started!");
-            self.running = VORExecutionState::Error("VOR Bind Error".to_string());
-        } else {
-            self.configs[status.index as usize].1 = status.status;
+        // Routes report counters every second so read every pending event
+        while let Some(event) = self.route_event_recvr.as_ref().and_then(|r| r.try_recv().ok()) {
+            self.handle_route_event(event);
+        }
+
+        // Routes and listeners report counters every second so read every pending status
+        loop {
+            let status = match self.router_msg_recvr.as_ref() {
+                Some(recvr) => match recvr.try_recv() {
+                    Ok(status) => status,
+                    Err(_e) => {
+                        return;
+                    }
+                },
+                None => return,
+            };
+            self.handle_status(status);
+        }
+    }
+
+    fn handle_route_event(&mut self, event: RouteEvent) {
+        match event {
+            RouteEvent::RateLimit(id, stats) => {
+                self.rate_limit_stats.insert(id, stats);
+            }
+            RouteEvent::Capture(capture) => self.capture_status = Some(capture),
+            RouteEvent::ReturnError(e) => self.return_error = e,
         }
     }
 
+    fn handle_status(&mut self, status: VORAppIdentifier) {
+        if let VORAppStatus::Listener(name, stats) = status.status {
+            match self.listener_stats.iter_mut().find(|l| l.0 == name) {
+                Some(l) => l.1 = stats,
+                None => self.listener_stats.push((name, stats)),
+            }
+        } else if status.index == -1 {
+            let msg = match status.status {
+                VORAppStatus::AppError(e) => e.msg,
+                _ => "VOR Bind Error".to_string(),
+            };
+            println!("[!] {}.. Not started!", msg);
+            self.running = VORExecutionState::Error(msg);
+        } else if let VORAppStatus::Stats(stats) = status.status {
+            self.route_stats.insert(status.index, stats);
+        } else if let VORAppStatus::Warning(warning) = status.status {
+            match warning {
+                Some(w) => self.route_warnings.insert(status.index, w),
+                None => self.route_warnings.remove(&status.index),
+            };
+        } else if let Some(app) = self
+            .configs
+            .iter_mut()
+            .find(|c| c.0.route_id == status.index)
+        {
+            // Disabled apps have no route so any status for them is stale
+            if let VORAppStatus::Disabled = app.1 {
+                return;
+            }
+            // Kept after the route is running again
+            if let VORAppStatus::Restarting(ref stats) = status.status {
+                self.route_restarts.insert(status.index, stats.clone());
+            }
+            app.1 = status.status;
+        }
+    }
+
+    // Record the listeners' datagrams to a file while routing
+    fn capture_controls(&mut self, ui: &mut egui::Ui) {
+        if let VORExecutionState::Stopped = self.running {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.group(|ui| {
+                ui.label("Capture file: ");
+                ui.text_edit_singleline(&mut self.capture_path);
+                if let Some(CaptureStatus::Recording(_)) = self.capture_status {
+                    if ui.button("Stop recording").clicked() {
+                        self.route_msg(RouterMsg::StopCapture);
+                    }
+                } else if ui.button("Record").clicked() {
+                    self.route_msg(RouterMsg::StartCapture(self.capture_path.clone()));
+                }
+                let color = match self.capture_status {
+                    Some(CaptureStatus::Failed(_)) => Color32::GOLD,
+                    _ => Color32::LIGHT_BLUE,
+                };
+                if let Some(ref s) = self.capture_status {
+                    ui.label(RichText::new(s.to_string()).color(color));
+                }
+            });
+        });
+    }
+
     fn status(&mut self, ui: &mut egui::Ui) {
         //update vor status
         self.status_refresh();
 
+        self.capture_controls(ui);
+
         ScrollArea::new([false, true]).show(ui, |ui| {
+            // Listener totals
+            for (name, stats) in &self.listener_stats {
+                ui.horizontal(|ui| {
+                    ui.group(|ui| {
+                        ui.label(format!("Listener: {}", name));
+                        ui.with_layout(Layout::right_to_left(), |ui| {
+                            ui.separator();
+                            ui.label(RichText::new(stats.to_string()).color(Color32::LIGHT_BLUE));
+                        });
+                    });
+                });
+            }
+
+            // App replies -> VRChat
+            if let Some(ref e) = self.return_error {
+                ui.horizontal(|ui| {
+                    ui.group(|ui| {
+                        ui.label("Return path");
+                        ui.with_layout(Layout::right_to_left(), |ui| {
+                            ui.separator();
+                            ui.add(Label::new(RichText::new(e).color(Color32::GOLD)).wrap(true));
+                        });
+                    });
+                });
+            }
+
             // App Statuses
             if self.configs.len() > 0 {
                 for i in 0..self.configs.len() {
                     let mut status_color = Color32::GREEN;
                     match self.configs[i].1 {
                         VORAppStatus::Running => {}
+                        VORAppStatus::Paused => status_color = Color32::YELLOW,
+                        VORAppStatus::Connecting => status_color = Color32::LIGHT_BLUE,
+                        VORAppStatus::Clients(_) => {}
+                        VORAppStatus::Disconnected(_) => status_color = Color32::GOLD,
+                        VORAppStatus::NotListening => status_color = Color32::GOLD,
                         VORAppStatus::Stopped => status_color = Color32::RED,
                         VORAppStatus::AppError(_) => status_color = Color32::GOLD,
+                        VORAppStatus::Restarting(_) => status_color = Color32::GOLD,
                         VORAppStatus::Disabled => status_color = Color32::RED,
+                        VORAppStatus::Stats(_) | VORAppStatus::Listener(..) | VORAppStatus::Warning(_) => {}
                     }
                     ui.horizontal(|ui| {
                         ui.group(|ui| {
@@ -505,6 +931,18 @@ impl VORGUI {
                                     )
                                     .wrap(true),
                                 );
+                                if let Some(warning) = self.route_warnings.get(&self.configs[i].0.route_id) {
+                                    ui.separator();
+                                    ui.label(RichText::new(format!("Warning: {}", warning)).color(Color32::GOLD));
+                                }
+                                if let Some(stats) = self.rate_limit_stats.get(&self.configs[i].0.route_id) {
+                                    ui.separator();
+                                    ui.label(RichText::new(stats.to_string()).color(Color32::YELLOW));
+                                }
+                                if let Some(stats) = self.route_stats.get(&self.configs[i].0.route_id) {
+                                    ui.separator();
+                                    ui.label(RichText::new(stats.to_string()).color(Color32::LIGHT_BLUE));
+                                }
                             });
                         });
                     });
@@ -530,7 +968,30 @@ impl VORGUI {
                 &mut self.vor_router_config.bind_port,
             ));
         });
-        /* For feature never ended up adding
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Multicast Group: ");
+            ui.add(egui::TextEdit::singleline(
+                &mut self.vor_router_config.multicast_group,
+            ));
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(
+                &mut self.vor_router_config.oscquery,
+                "Advertise over OSCQuery",
+            );
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Metrics Host: ");
+            ui.add(egui::TextEdit::singleline(
+                &mut self.vor_router_config.metrics_host,
+            ));
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Metrics Port: ");
+            ui.add(egui::TextEdit::singleline(
+                &mut self.vor_router_config.metrics_port,
+            ));
+        });
         ui.horizontal_wrapped(|ui| {
             ui.label("VRChat Host: ");
             ui.add(egui::TextEdit::singleline(
@@ -543,7 +1004,6 @@ impl VORGUI {
                 &mut self.vor_router_config.vrc_port,
             ));
         });
-        */
         ui.horizontal_wrapped(|ui| {
             ui.label("VOR Buffer Queue Size: ");
             ui.add(egui::TextEdit::singleline(
@@ -551,6 +1011,39 @@ impl VORGUI {
             ));
         });
 
+        ui.separator();
+        ui.add_space(1.0);
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Extra listeners");
+            ui.with_layout(Layout::right_to_left(), |ui| {
+                if ui.button("Add").clicked() {
+                    self.vor_router_config.listeners.push(ListenerConfig {
+                        name: format!("Listener {}", self.vor_router_config.listeners.len() + 1),
+                        bind_host: "127.0.0.1".to_string(),
+                        bind_port: String::new(),
+                    });
+                }
+            });
+        });
+        ui.separator();
+        let mut remove_listener = None;
+        for (i, l) in self.vor_router_config.listeners.iter_mut().enumerate() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Name: ");
+                ui.add(egui::TextEdit::singleline(&mut l.name).desired_width(90.));
+                ui.label("Host: ");
+                ui.add(egui::TextEdit::singleline(&mut l.bind_host).desired_width(90.));
+                ui.label("Port: ");
+                ui.add(egui::TextEdit::singleline(&mut l.bind_port).desired_width(50.));
+                if ui.button(RichText::new("-").color(Color32::RED).monospace()).clicked() {
+                    remove_listener = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove_listener {
+            self.vor_router_config.listeners.remove(i);
+        }
+
         ui.separator();
         ui.add_space(1.0);
         ui.label("Routing mode");
@@ -645,17 +1138,14 @@ impl VORGUI {
         // Create main router thread - 1 channel store TX in GUI object
         // router thread recv msgs from GUI thread and controls child threads each with their own channel to comm with router thread
         // Generate / Start OSC threads here
-        let mut ids = -1;
         let confs: Vec<(VORConfig, i64)> = self
             .configs
             .iter()
             .filter_map(|c| {
                 if let VORAppStatus::Disabled = c.1 {
-                    ids += 1;
                     None
                 } else {
-                    ids += 1;
-                    Some((c.0.config_data.clone(), ids))
+                    Some((c.0.config_data.clone(), c.0.route_id))
                 }
             })
             .collect();
@@ -663,47 +1153,33 @@ impl VORGUI {
         let (router_tx, router_rx): (Sender<RouterMsg>, Receiver<RouterMsg>) = mpsc::channel();
         let (app_stat_tx, app_stat_rx): (Sender<VORAppIdentifier>, Receiver<VORAppIdentifier>) =
             mpsc::channel();
+        let (route_event_tx, route_event_rx): (Sender<RouteEvent>, Receiver<RouteEvent>) = mpsc::channel();
         self.router_channel = Some(router_tx);
         self.router_msg_recvr = Some(app_stat_rx);
-
-        let bind_target = format!(
-            "{}:{}",
-            self.vor_router_config.bind_host, self.vor_router_config.bind_port
-        );
-        let vor_buf_size = match self.vor_router_config.vor_buffer_size.parse::<usize>() {
-            Ok(s) => s,
+        self.route_event_recvr = Some(route_event_rx);
+        self.rate_limit_stats.clear();
+        self.route_stats.clear();
+        self.route_warnings.clear();
+        self.route_restarts.clear();
+        self.listener_stats.clear();
+        self.capture_status = None;
+        self.return_error = None;
+
+        let mut opts = match RouterOptions::new(&self.vor_router_config, self.pf.clone(), self.vrc_pf.clone()) {
+            Ok(o) => o,
             Err(_) => {
                 self.router_channel = None;
                 //self.router_msg_recvr = None;
                 return;
             }
         };
+        opts.capture_path = self.vc_args.capture.clone();
+        opts.debug_sender = self
+            .route_debug
+            .as_ref()
+            .map(|rd| rd.sig_channel_handler.debug_in.tx.clone());
 
-        let pf = self.pf.clone();
-        let async_mode = self.vor_router_config.async_mode;
-
-        let debug_sender = match &self.route_debug {
-            Some(rd) => Some(rd.sig_channel_handler.debug_in.tx.clone()),
-            None => None,
-        };
-        let debug_config = match &self.route_debug {
-            Some(rd) => Some(rd.options.clone()),
-            None => None,
-        };
-
-        thread::spawn(move || {
-            route_main(
-                bind_target,
-                router_rx,
-                app_stat_tx,
-                confs,
-                pf,
-                vor_buf_size,
-                async_mode,
-                debug_sender,
-                debug_config,
-            );
-        });
+        thread::spawn(move || route_main(opts, confs, router_rx, app_stat_tx, route_event_tx));
 
         self.running = VORExecutionState::Running;
     }
@@ -722,11 +1198,19 @@ impl VORGUI {
         self.running = VORExecutionState::Stopped;
         thread::sleep(std::time::Duration::from_secs(1));
 
-        if self.vor_router_config.async_mode {
-            for app_conf in &mut self.configs {
-                if let VORAppStatus::Running = app_conf.1 {
-                    app_conf.1 = VORAppStatus::Stopped;
-                }
+        for app_conf in &mut self.configs {
+            // Paused and restarting routes are already stopped so they never report
+            if let VORAppStatus::Paused | VORAppStatus::Restarting(_) = app_conf.1 {
+                app_conf.1 = VORAppStatus::Stopped;
+            }
+        }
+    }
+
+    // Send a route change to the router if it is running
+    fn route_msg(&self, msg: RouterMsg) {
+        if let VORExecutionState::Running = self.running {
+            if let Some(router_channel) = &self.router_channel {
+                let _ = router_channel.send(msg);
             }
         }
     }
@@ -781,6 +1265,36 @@ impl VORGUI {
                 }
                 return AppConfigCheck::IV(InputValidation::BP(s));
             }
+            InputValidation::RW(e) => {
+                if add_new {
+                    self.configs.pop();
+                }
+                return AppConfigCheck::IV(InputValidation::RW(e));
+            }
+            InputValidation::RL(s) => {
+                if add_new {
+                    self.configs.pop();
+                }
+                return AppConfigCheck::IV(InputValidation::RL(s));
+            }
+            InputValidation::DD(s) => {
+                if add_new {
+                    self.configs.pop();
+                }
+                return AppConfigCheck::IV(InputValidation::DD(s));
+            }
+            InputValidation::UP(s) => {
+                if add_new {
+                    self.configs.pop();
+                }
+                return AppConfigCheck::IV(InputValidation::UP(s));
+            }
+            InputValidation::MT(s) => {
+                if add_new {
+                    self.configs.pop();
+                }
+                return AppConfigCheck::IV(InputValidation::MT(s));
+            }
         }
 
         match self.check_app_conflicts(app_index) {
@@ -867,17 +1381,46 @@ impl VORGUI {
     }
 
     fn check_app_inputs(&mut self, app_index: usize) -> InputValidation {
-        if !check_valid_ipv4(&self.configs[app_index].0.config_data.app_host) {
-            return InputValidation::AH(false);
+        // Unix socket routes only need the socket path
+        if self.configs[app_index].0.config_data.transport == Transport::UnixDgram {
+            if self.configs[app_index].0.config_data.unix_path.trim().is_empty() {
+                return InputValidation::UP(false);
+            }
+        } else {
+            if !check_valid_host(&self.configs[app_index].0.config_data.app_host) {
+                return InputValidation::AH(false);
+            }
+
+            /*
+            if !check_valid_host(&self.configs[app_index].0.config_data.bind_host) {
+                return InputValidation::BH(false);
+            }*/
+
+            if !check_valid_port(&self.configs[app_index].0.config_data.app_port) {
+                return InputValidation::AP(false);
+            }
+
+            if !check_valid_ttl(&self.configs[app_index].0.config_data.multicast_ttl) {
+                return InputValidation::MT(false);
+            }
         }
 
-        /*
-        if !check_valid_ipv4(&self.configs[app_index].0.config_data.bind_host) {
-            return InputValidation::BH(false);
-        }*/
+        if let Some(rules) = &self.configs[app_index].0.config_data.rewrite {
+            if let Err(e) = AddressRewriter::new(rules) {
+                return InputValidation::RW(e);
+            }
+        }
+
+        if let Some(rate_limit) = &self.configs[app_index].0.config_data.rate_limit {
+            if !rate_limit.is_valid() {
+                return InputValidation::RL(false);
+            }
+        }
 
-        if !check_valid_port(&self.configs[app_index].0.config_data.app_port) {
-            return InputValidation::AP(false);
+        if let Some(dedup) = &self.configs[app_index].0.config_data.dedup {
+            if !dedup.is_valid() {
+                return InputValidation::DD(false);
+            }
         }
 
         /*
@@ -888,6 +1431,47 @@ impl VORGUI {
         return InputValidation::CLEAN;
     }
 
+    // Save a new app's config and start its route
+    fn add_app_config(&mut self, mut app: VORConfigWrapper) -> AppConfigCheck {
+        #[cfg(target_os = "windows")]
+        {
+            app.config_path = format!(
+                "{}\\VORAppConfigs\\{}.json",
+                vor_root().expect("[-] Roaming directory can't be found!"),
+                app.config_data.app_name
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            app.config_path = format!(
+                "{}/.vor/VORAppConfigs/{}.json",
+                get_user_home_dir(),
+                app.config_data.app_name
+            );
+        }
+
+        if file_exists(&app.config_path) {
+            return AppConfigCheck::AC(AppConflicts::CONFLICT((
+                app.config_data.app_name,
+                "App Name".to_string(),
+            )));
+        }
+
+        self.configs
+            .push((app, VORAppStatus::Stopped, AppConfigState::SAVED));
+        let check = self.save_app_config(self.configs.len() - 1, true);
+        if let AppConfigCheck::SUCCESS = check {
+            self.next_route_id += 1;
+            let new_app = &self.configs[self.configs.len() - 1].0;
+            self.route_msg(RouterMsg::AddRoute(
+                new_app.config_data.clone(),
+                new_app.route_id,
+            ));
+        }
+        check
+    }
+
     fn add_app(&mut self, ui: &mut egui::Ui) {
         ui.group(|ui| {
             if self.adding_new_app {
@@ -900,6 +1484,46 @@ impl VORGUI {
                 ui.horizontal_wrapped(|ui| {
                     ui.label("App Port: ");ui.add(egui::TextEdit::singleline(&mut self.new_app.as_mut().unwrap().config_data.app_port));
                 });
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Transport: ");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.transport, Transport::Udp, "UDP");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.transport, Transport::Tcp, "TCP (SLIP)");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.transport, Transport::WebSocket, "WebSocket");
+                    if cfg!(unix) {
+                        ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.transport, Transport::UnixDgram, "Unix socket");
+                    }
+                });
+                if is_multicast_host(&self.new_app.as_ref().unwrap().config_data) {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("Multicast TTL: ");ui.add(egui::TextEdit::singleline(&mut self.new_app.as_mut().unwrap().config_data.multicast_ttl));
+                        ui.checkbox(&mut self.new_app.as_mut().unwrap().config_data.multicast_loop, "Loopback");
+                    });
+                }
+                if is_unicast_v4_host(&self.new_app.as_ref().unwrap().config_data) {
+                    ui.checkbox(&mut self.new_app.as_mut().unwrap().config_data.broadcast, "Subnet broadcast address");
+                }
+                if self.new_app.as_ref().unwrap().config_data.transport == Transport::UnixDgram {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("Socket path: ");ui.add(egui::TextEdit::singleline(&mut self.new_app.as_mut().unwrap().config_data.unix_path));
+                    });
+                }
+                if self.new_app.as_ref().unwrap().config_data.transport == Transport::WebSocket {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("WebSocket format: ");
+                        ui.radio_value(&mut self.new_app.as_mut().unwrap().config_data.ws_format, WsFormat::Binary, "Binary OSC");
+                        ui.radio_value(&mut self.new_app.as_mut().unwrap().config_data.ws_format, WsFormat::Json, "JSON");
+                    });
+                }
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Queue policy: ");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.queue_policy, QueuePolicy::DropOldest, "Drop oldest");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.queue_policy, QueuePolicy::DropNewest, "Drop newest");
+                    ui.selectable_value(&mut self.new_app.as_mut().unwrap().config_data.queue_policy, QueuePolicy::Coalesce, "Coalesce by address");
+                });
+                if self.new_app.as_ref().unwrap().config_data.transport == Transport::Udp {
+                    ui.checkbox(&mut self.new_app.as_mut().unwrap().config_data.health_check, "Detect when the app is not listening");
+                }
+                ui.checkbox(&mut self.new_app.as_mut().unwrap().config_data.vrc_return, "Return app replies to VRChat");
                 /*
                 ui.horizontal_wrapped(|ui| {
                     ui.label("Bind Host:");ui.add(egui::TextEdit::singleline(&mut self.new_app.as_mut().unwrap().config_data.bind_host));
@@ -927,42 +1551,10 @@ impl VORGUI {
                                 self.new_app_cf_exists_err = AppConfigCheck::SUCCESS;
                             }
                             if ui.button(RichText::new("Add")).clicked() {
-
-                                #[cfg(target_os = "windows")]
-                                {
-                                    self.new_app.as_mut().unwrap().config_path = format!("{}\\VORAppConfigs\\{}.json", vor_root().expect("[-] Roaming directory can't be found!"), self.new_app.as_ref().unwrap().config_data.app_name);
-                                }
-
-                                #[cfg(target_os = "linux")]
-                                {
-                                    self.new_app.as_mut().unwrap().config_path = format!("{}/.vor/VORAppConfigs/{}.json", get_user_home_dir(), self.new_app.as_ref().unwrap().config_data.app_name);
-                                }
-
-                                if !file_exists(&self.new_app.as_ref().unwrap().config_path) {
-                                    self.configs.push((self.new_app.as_ref().unwrap().clone(), VORAppStatus::Stopped, AppConfigState::SAVED));
-
-                                    match self.save_app_config(self.configs.len()-1, self.adding_new_app) {
-                                        AppConfigCheck::AC(ac) => {
-                                            self.new_app_cf_exists_err = AppConfigCheck::AC(ac);
-                                            //println!("[!!] Add new -> AC err");
-                                        },
-                                        AppConfigCheck::IV(iv) => {
-                                            self.new_app_cf_exists_err = AppConfigCheck::IV(iv);
-                                            //println!("[!!] Add new -> IV err");
-                                        },
-                                        AppConfigCheck::SUCCESS => {
-                                            self.adding_new_app = false;
-                                            self.new_app_cf_exists_err = AppConfigCheck::SUCCESS;
-                                        }
-                                    }
-                                } else {//println!("[!] Config conflict!");
-
-                                    /*
-                                    if self.vor_router_config.bind_port == self.new_app.as_ref().unwrap().config_data.bind_port {
-                                        self.new_app_cf_exists_err = AppConfigCheck::AC(AppConflicts::CONFLICT((self.new_app.as_ref().unwrap().config_data.bind_port.clone(), "VOR bind port conflict".to_string())));
-                                    } else {
-                                        self.new_app_cf_exists_err = AppConfigCheck::AC(AppConflicts::CONFLICT((self.new_app.as_ref().unwrap().config_data.app_name.clone(), "App Name".to_string())));
-                                    }*/
+                                let new_app = self.new_app.as_ref().unwrap().clone();
+                                self.new_app_cf_exists_err = self.add_app_config(new_app);
+                                if let AppConfigCheck::SUCCESS = self.new_app_cf_exists_err {
+                                    self.adding_new_app = false;
                                 }
                             }
                         });
@@ -975,13 +1567,8 @@ impl VORGUI {
                         if ui.button(RichText::new("+").color(Color32::GREEN).monospace()).clicked() {
                             self.new_app = Some(VORConfigWrapper {
                                 config_path: String::new(),
-                                config_data: VORConfig {
-                                    app_port: "9100".to_string(),
-                                    app_host: "127.0.0.1".to_string(),
-                                    //bind_port: "9101".to_string(),
-                                    //bind_host: "127.0.0.1".to_string(),
-                                    app_name: "New App".to_string(),
-                                },
+                                config_data: VORConfig::default(),
+                                route_id: self.next_route_id,
                             });// new_app defaults
                             self.adding_new_app = true;// Being added
                         }// New button
@@ -991,6 +1578,120 @@ impl VORGUI {
         });
     }
 
+    // Apps advertised over mDNS/OSCQuery on this machine
+    fn discover_apps(&mut self, ui: &mut egui::Ui) {
+        if let Some(discovery) = &self.discovery {
+            for app in discovery.poll() {
+                // Apps advertising both services are listed once (every app is on this machine). Prefer the OSCQuery info.
+                match self.discovered_apps.iter_mut().find(|a| a.port == app.port) {
+                    Some(a) => {
+                        if !app.addresses.is_empty() {
+                            *a = app;
+                        }
+                    }
+                    None => self.discovered_apps.push(app),
+                }
+            }
+            ui.ctx().request_repaint();
+        }
+
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Discover apps (mDNS/OSCQuery)");
+                ui.with_layout(Layout::right_to_left(), |ui| {
+                    if self.discovery.is_some() {
+                        if ui.button(RichText::new("Stop").color(Color32::RED)).clicked() {
+                            self.discovery = None;
+                        }
+                    } else if ui.button("Scan").clicked() {
+                        self.discovered_apps.clear();
+                        match Discovery::new() {
+                            Ok(d) => {
+                                self.discovery = Some(d);
+                                self.discovery_err = None;
+                            }
+                            Err(e) => self.discovery_err = Some(format!("Discovery failed: {}", e)),
+                        }
+                    }
+                });
+            });
+            ui.checkbox(
+                &mut self.discovery_whitelist,
+                "Whitelist the addresses the app accepts",
+            );
+            if let Some(ref e) = self.discovery_err {
+                ui.colored_label(Color32::RED, e);
+            }
+
+            ui.separator();
+            if self.discovery.is_some() && self.discovered_apps.is_empty() {
+                ui.label("Searching..");
+            }
+
+            let mut add = None;
+            for (i, app) in self.discovered_apps.iter().enumerate() {
+                // VOR's own listeners and VRChat also advertise OSC services
+                let port = app.port.to_string();
+                if port == self.vor_router_config.vrc_port
+                    || self
+                        .vor_router_config
+                        .listener_targets()
+                        .iter()
+                        .any(|(_, target)| target.ends_with(&format!(":{}", port)))
+                {
+                    continue;
+                }
+
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(format!(
+                        "{} ({}) {} addresses",
+                        app.name,
+                        host_port(&app.host, &port),
+                        app.addresses.len()
+                    ));
+                    ui.with_layout(Layout::right_to_left(), |ui| {
+                        if ui.button(RichText::new("+").color(Color32::GREEN).monospace()).clicked() {
+                            add = Some(i);
+                        }
+                    });
+                });
+            }
+
+            if let Some(i) = add {
+                let app = &self.discovered_apps[i];
+                let pf = if self.discovery_whitelist && !app.addresses.is_empty() {
+                    Some(PacketFilter {
+                        enabled: true,
+                        wl_enabled: true,
+                        address_wl: app.addresses.iter().map(|a| (a.clone(), false)).collect(),
+                        ..Default::default()
+                    })
+                } else {
+                    None
+                };
+                let new_app = VORConfigWrapper {
+                    config_path: String::new(),
+                    config_data: VORConfig {
+                        app_name: app.name.clone(),
+                        app_host: app.host.clone(),
+                        app_port: app.port.to_string(),
+                        pf,
+                        ..Default::default()
+                    },
+                    route_id: self.next_route_id,
+                };
+                self.discovery_err = match self.add_app_config(new_app) {
+                    AppConfigCheck::SUCCESS => {
+                        self.discovered_apps.remove(i);
+                        None
+                    }
+                    AppConfigCheck::AC(ac) => Some(ac.to_string()),
+                    AppConfigCheck::IV(iv) => Some(iv.to_string()),
+                };
+            }
+        });
+    }
+
     fn gui_header(&mut self, ui: &mut egui::Ui) {
         ui.vertical_centered(|ui| {
             ui.heading("VOR");
@@ -1043,6 +1744,109 @@ impl VORGUI {
                         ui.horizontal_wrapped(|ui| {
                             ui.label("App Port: ");ui.add(egui::TextEdit::singleline(&mut self.configs[i].0.config_data.app_port));
                         });
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label("Transport: ");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.transport, Transport::Udp, "UDP");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.transport, Transport::Tcp, "TCP (SLIP)");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.transport, Transport::WebSocket, "WebSocket");
+                            if cfg!(unix) {
+                                ui.selectable_value(&mut self.configs[i].0.config_data.transport, Transport::UnixDgram, "Unix socket");
+                            }
+                        });
+                        if is_multicast_host(&self.configs[i].0.config_data) {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label("Multicast TTL: ");ui.add(egui::TextEdit::singleline(&mut self.configs[i].0.config_data.multicast_ttl));
+                                ui.checkbox(&mut self.configs[i].0.config_data.multicast_loop, "Loopback");
+                            });
+                        }
+                        if is_unicast_v4_host(&self.configs[i].0.config_data) {
+                            ui.checkbox(&mut self.configs[i].0.config_data.broadcast, "Subnet broadcast address");
+                        }
+                        if self.configs[i].0.config_data.transport == Transport::UnixDgram {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label("Socket path: ");ui.add(egui::TextEdit::singleline(&mut self.configs[i].0.config_data.unix_path));
+                            });
+                        }
+                        if self.configs[i].0.config_data.transport == Transport::WebSocket {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label("WebSocket format: ");
+                                ui.radio_value(&mut self.configs[i].0.config_data.ws_format, WsFormat::Binary, "Binary OSC");
+                                ui.radio_value(&mut self.configs[i].0.config_data.ws_format, WsFormat::Json, "JSON");
+                            });
+                        }
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label("Queue policy: ");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.queue_policy, QueuePolicy::DropOldest, "Drop oldest");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.queue_policy, QueuePolicy::DropNewest, "Drop newest");
+                            ui.selectable_value(&mut self.configs[i].0.config_data.queue_policy, QueuePolicy::Coalesce, "Coalesce by address");
+                        });
+                        if self.configs[i].0.config_data.transport == Transport::Udp {
+                            ui.checkbox(&mut self.configs[i].0.config_data.health_check, "Detect when the app is not listening");
+                        }
+                        ui.checkbox(&mut self.configs[i].0.config_data.vrc_return, "Return app replies to VRChat");
+
+                        let mut route_pf_enabled = self.configs[i].0.config_data.pf.is_some();
+                        if ui.checkbox(&mut route_pf_enabled, "Route packet filter").changed() {
+                            self.configs[i].0.config_data.pf = if route_pf_enabled {
+                                Some(PacketFilter { enabled: true, ..Default::default() })
+                            } else {
+                                None
+                            };
+                        }
+                        if route_pf_enabled {
+                            self.route_pf_editor(ui, i);
+                        }
+
+                        let mut rewrite_enabled = self.configs[i].0.config_data.rewrite.is_some();
+                        if ui.checkbox(&mut rewrite_enabled, "Address rewriting").changed() {
+                            self.configs[i].0.config_data.rewrite = if rewrite_enabled {
+                                Some(RewriteRules::default())
+                            } else {
+                                None
+                            };
+                        }
+                        if let Some(rules) = self.configs[i].0.config_data.rewrite.as_mut() {
+                            ui.group(|ui| {
+                                rewrite_rule_list(ui, "Exact renames", "Address", rules, |r| &mut r.rename);
+                                rewrite_rule_list(ui, "Prefix replacements", "Prefix", rules, |r| &mut r.prefix);
+                                rewrite_rule_list(ui, "Regex rewrites", "Regex", rules, |r| &mut r.regex);
+                            });
+                        }
+
+                        let mut rate_limit_enabled = self.configs[i].0.config_data.rate_limit.is_some();
+                        if ui.checkbox(&mut rate_limit_enabled, "Rate limit").changed() {
+                            self.configs[i].0.config_data.rate_limit = if rate_limit_enabled {
+                                Some(RateLimit::default())
+                            } else {
+                                None
+                            };
+                        }
+                        if let Some(rate_limit) = self.configs[i].0.config_data.rate_limit.as_mut() {
+                            ui.group(|ui| {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label("Max messages per second: ");ui.add(egui::TextEdit::singleline(&mut rate_limit.max_per_second));
+                                });
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label("Coalescing window (ms): ");ui.add(egui::TextEdit::singleline(&mut rate_limit.coalesce_ms));
+                                });
+                            });
+                        }
+
+                        let mut dedup_enabled = self.configs[i].0.config_data.dedup.is_some();
+                        if ui.checkbox(&mut dedup_enabled, "Skip unchanged values").changed() {
+                            self.configs[i].0.config_data.dedup = if dedup_enabled {
+                                Some(Dedup::default())
+                            } else {
+                                None
+                            };
+                        }
+                        if let Some(dedup) = self.configs[i].0.config_data.dedup.as_mut() {
+                            ui.group(|ui| {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label("Force resend every (seconds): ");ui.add(egui::TextEdit::singleline(&mut dedup.keepalive_secs));
+                                });
+                            });
+                        }
                         /*
                         ui.horizontal_wrapped(|ui| {
                             ui.label("Bind Host:");ui.add(egui::TextEdit::singleline(&mut self.configs[i].0.config_data.bind_host));
@@ -1082,6 +1886,10 @@ impl VORGUI {
                                     match self.save_app_config(i, false) {
                                         AppConfigCheck::SUCCESS => {
                                             self.configs[i].2 = AppConfigState::SAVED;// Not being edited
+                                            // Apply the edit to the live route
+                                            if let VORAppStatus::Running | VORAppStatus::Connecting | VORAppStatus::Disconnected(_) | VORAppStatus::Clients(_) | VORAppStatus::NotListening | VORAppStatus::AppError(_) | VORAppStatus::Restarting(_) = self.configs[i].1 {
+                                                self.route_msg(RouterMsg::UpdateRoute(self.configs[i].0.config_data.clone(), self.configs[i].0.route_id));
+                                            }
                                         },
                                         AppConfigCheck::AC(ac) => {
                                             // Conflicting input errors
@@ -1102,34 +1910,47 @@ impl VORGUI {
 
                         ui.horizontal(|ui| {
                             ui.label(self.configs[i].0.config_data.app_name.as_str());
+                            if let Some(restarts) = self.route_restarts.get(&self.configs[i].0.route_id) {
+                                ui.label(RichText::new(restarts.to_string()).color(Color32::GOLD));
+                            }
 
                             ui.with_layout(Layout::right_to_left(), |ui| {
                                 //if !self.running {
+                                let route_id = self.configs[i].0.route_id;
+                                if ui.button(RichText::new("-").color(Color32::RED).monospace()).clicked() {
+                                    self.route_msg(RouterMsg::RemoveRoute(route_id));
+                                    fs::remove_file(&self.configs[i].0.config_path).unwrap();
+                                    self.configs.remove(i);
+                                    return;
+                                }
+                                if ui.button(RichText::new("Edit")).clicked() {
+                                    self.configs[i].2 = AppConfigState::EDIT(AppConfigCheck::SUCCESS);// Being edited
+                                }
                                 match &self.configs[i].1 {
-                                    VORAppStatus::Running => {
-                                        ui.colored_label(Color32::RED, "Locked");
-                                    },
-                                    VORAppStatus::Stopped | VORAppStatus::Disabled => {
-                                        if ui.button(RichText::new("-").color(Color32::RED).monospace()).clicked() {
-                                            fs::remove_file(&self.configs[i].0.config_path).unwrap();
-                                            self.configs.remove(i);
-                                            return;
-                                        }
-                                        if ui.button(RichText::new("Edit")).clicked() {
-                                            self.configs[i].2 = AppConfigState::EDIT(AppConfigCheck::SUCCESS);// Being edited
+                                    VORAppStatus::Running | VORAppStatus::Connecting | VORAppStatus::Disconnected(_) | VORAppStatus::Clients(_) | VORAppStatus::NotListening | VORAppStatus::Restarting(_) => {
+                                        if ui.button(RichText::new("Pause")).clicked() {
+                                            self.route_msg(RouterMsg::PauseRoute(route_id));
                                         }
-                                        if let VORAppStatus::Disabled = self.configs[i].1 {
-                                            if ui.button(RichText::new("Enable")).clicked() {
-                                                self.configs[i].1 = VORAppStatus::Stopped;
-                                            }
-                                        } else {
-                                            if ui.button(RichText::new("Disable")).clicked() {
-                                                self.configs[i].1 = VORAppStatus::Disabled;
-                                            }
+                                    },
+                                    VORAppStatus::Paused => {
+                                        if ui.button(RichText::new("Resume")).clicked() {
+                                            self.route_msg(RouterMsg::AddRoute(self.configs[i].0.config_data.clone(), route_id));
                                         }
                                     },
                                     VORAppStatus::AppError(_e) => {
                                         ui.colored_label(Color32::RED, "Error");
+                                    },
+                                    VORAppStatus::Stopped | VORAppStatus::Disabled | VORAppStatus::Stats(_) | VORAppStatus::Listener(..) | VORAppStatus::Warning(_) => {},
+                                }
+                                if let VORAppStatus::Disabled = self.configs[i].1 {
+                                    if ui.button(RichText::new("Enable")).clicked() {
+                                        self.configs[i].1 = VORAppStatus::Stopped;
+                                        self.route_msg(RouterMsg::AddRoute(self.configs[i].0.config_data.clone(), route_id));
+                                    }
+                                } else {
+                                    if ui.button(RichText::new("Disable")).clicked() {
+                                        self.route_msg(RouterMsg::RemoveRoute(route_id));
+                                        self.configs[i].1 = VORAppStatus::Disabled;
                                     }
                                 }
                             });
@@ -1140,28 +1961,73 @@ impl VORGUI {
         } // For list
     }
 
-    fn pf_buttons(&mut self, ui: &mut egui::Ui) {
-        if !self.pf.enabled {
+    fn route_pf_editor(&mut self, ui: &mut egui::Ui, app_index: usize) {
+        let target = PFTarget::Route(app_index);
+        ui.group(|ui| {
+            self.pf_buttons(ui, target);
+            if self.pf_ref(target).wl_enabled {
+                ui.label(RichText::new("Route Whitelist"));
+                self.pf_whitelist(ui, target);
+                self.add_pf_wl(ui, target);
+            } else if self.pf_ref(target).bl_enabled {
+                ui.label(RichText::new("Route Blacklist"));
+                self.pf_blacklist(ui, target);
+                self.add_pf_bl(ui, target);
+            }
+        });
+    }
+
+    fn pf_buttons(&mut self, ui: &mut egui::Ui, target: PFTarget) {
+        if !self.pf_ref(target).enabled {
             return;
         }
-        ui.checkbox(&mut self.pf.filter_bad_packets, "Filter bad packets");
-        if !self.pf.bl_enabled {
-            ui.checkbox(&mut self.pf.wl_enabled, "Whitelisting");
+        ui.checkbox(&mut self.pf_mut(target).filter_bad_packets, "Filter bad packets");
+        if !self.pf_ref(target).bl_enabled {
+            ui.checkbox(&mut self.pf_mut(target).wl_enabled, "Whitelisting");
         }
-        if !self.pf.wl_enabled {
-            ui.checkbox(&mut self.pf.bl_enabled, "Blacklisting");
+        if !self.pf_ref(target).wl_enabled {
+            ui.checkbox(&mut self.pf_mut(target).bl_enabled, "Blacklisting");
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Partially allowed bundles: ");
+            ui.radio_value(&mut self.pf_mut(target).bundle_mode, BundleMode::Rebuild, "Rebuild bundle");
+            ui.radio_value(&mut self.pf_mut(target).bundle_mode, BundleMode::Flatten, "Flatten to messages");
+        });
+    }
+
+    fn pf_ref(&self, target: PFTarget) -> &PacketFilter {
+        match target {
+            PFTarget::Incoming => &self.pf,
+            PFTarget::Return => &self.vrc_pf,
+            PFTarget::Route(i) => self.configs[i].0.config_data.pf.as_ref().unwrap(),
         }
     }
 
-    fn save_pf_config(&mut self) {
+    fn pf_mut(&mut self, target: PFTarget) -> &mut PacketFilter {
+        match target {
+            PFTarget::Incoming => &mut self.pf,
+            PFTarget::Return => &mut self.vrc_pf,
+            PFTarget::Route(i) => self.configs[i].0.config_data.pf.as_mut().unwrap(),
+        }
+    }
+
+    fn save_pf_config(&mut self, target: PFTarget) {
+        let (pf_file, pf) = match target {
+            PFTarget::Incoming => ("VOR_PF.json", &self.pf),
+            PFTarget::Return => ("VOR_VRC_PF.json", &self.vrc_pf),
+            // Route filters are saved with their app config
+            PFTarget::Route(_) => return,
+        };
+
         #[cfg(target_os = "windows")]
         {
             fs::write(
                 format!(
-                    "{}\\VOR_PF.json",
-                    vor_root().expect("[-] Roaming directory can't be found!")
+                    "{}\\{}",
+                    vor_root().expect("[-] Roaming directory can't be found!"),
+                    pf_file
                 ),
-                serde_json::to_string(&self.pf).unwrap(),
+                serde_json::to_string(pf).unwrap(),
             )
             .unwrap();
         }
@@ -1169,35 +2035,35 @@ impl VORGUI {
         #[cfg(target_os = "linux")]
         {
             fs::write(
-                format!("{}/.vor/VOR_PF.json", get_user_home_dir()),
-                serde_json::to_string(&self.pf).unwrap(),
+                format!("{}/.vor/{}", get_user_home_dir(), pf_file),
+                serde_json::to_string(pf).unwrap(),
             )
             .unwrap();
         }
     }
 
-    fn pf_whitelist(&mut self, ui: &mut egui::Ui) {
-        if self.pf.wl_enabled {
-            let wl_add_count = self.pf.address_wl.len();
+    fn pf_whitelist(&mut self, ui: &mut egui::Ui, target: PFTarget) {
+        if self.pf_ref(target).wl_enabled {
+            let wl_add_count = self.pf_ref(target).address_wl.len();
 
             if wl_add_count >= 1 {
                 for i in 0..wl_add_count {
                     let mut removed = false;
-                    if !self.pf.address_wl[i].1 {
+                    if !self.pf_ref(target).address_wl[i].1 {
                         ui.horizontal(|ui| {
                             ui.group(|ui| {
-                                ui.label(egui::RichText::new(&self.pf.address_wl[i].0).monospace());
+                                ui.label(egui::RichText::new(&self.pf_ref(target).address_wl[i].0).monospace());
                                 ui.with_layout(Layout::right_to_left(), |ui| {
                                     if ui
                                         .button(RichText::new("-").monospace().color(Color32::RED))
                                         .clicked()
                                     {
-                                        self.pf.address_wl.remove(i);
+                                        self.pf_mut(target).address_wl.remove(i);
                                         removed = true;
                                     }
 
                                     if ui.button(RichText::new("Edit").monospace()).clicked() {
-                                        self.pf.address_wl[i].1 = true;
+                                        self.pf_mut(target).address_wl[i].1 = true;
                                     }
                                 });
                             });
@@ -1212,10 +2078,10 @@ impl VORGUI {
                                 ui.with_layout(Layout::right_to_left(), |ui| {
                                     if ui.button("Save").clicked() {
                                         // Save to file
-                                        self.pf.address_wl[i].1 = false;
-                                        self.save_pf_config();
+                                        self.pf_mut(target).address_wl[i].1 = false;
+                                        self.save_pf_config(target);
                                     }
-                                    ui.text_edit_singleline(&mut self.pf.address_wl[i].0);
+                                    ui.text_edit_singleline(&mut self.pf_mut(target).address_wl[i].0);
                                 });
                             });
                         });
@@ -1225,7 +2091,7 @@ impl VORGUI {
         }
     }
 
-    fn add_pf_wl(&mut self, ui: &mut egui::Ui) {
+    fn add_pf_wl(&mut self, ui: &mut egui::Ui, target: PFTarget) {
         // if not adding
         if !self.pf_wl_new.1 {
             ui.horizontal(|ui| {
@@ -1237,7 +2103,7 @@ impl VORGUI {
                             .clicked()
                         {
                             //self.pf.wl_editing = true;
-                            self.save_pf_config();
+                            self.save_pf_config(target);
                             self.pf_wl_new.1 = true;
                         }
                     });
@@ -1258,38 +2124,39 @@ impl VORGUI {
                     }
                     if ui.button("Add filter").clicked() {
                         self.pf_wl_new.1 = false;
-                        self.pf.address_wl.push(self.pf_wl_new.clone());
+                        let new_filter = self.pf_wl_new.clone();
+                        self.pf_mut(target).address_wl.push(new_filter);
                         self.pf_wl_new.0.clear();
-                        self.save_pf_config();
+                        self.save_pf_config(target);
                     }
                 });
             });
         }
     }
 
-    fn pf_blacklist(&mut self, ui: &mut egui::Ui) {
-        if self.pf.bl_enabled {
-            let bl_add_count = self.pf.address_bl.len();
+    fn pf_blacklist(&mut self, ui: &mut egui::Ui, target: PFTarget) {
+        if self.pf_ref(target).bl_enabled {
+            let bl_add_count = self.pf_ref(target).address_bl.len();
 
             if bl_add_count >= 1 {
                 for i in 0..bl_add_count {
                     let mut removed = false;
-                    if !self.pf.address_bl[i].1 {
+                    if !self.pf_ref(target).address_bl[i].1 {
                         ui.horizontal(|ui| {
                             ui.group(|ui| {
-                                ui.label(egui::RichText::new(&self.pf.address_bl[i].0).monospace());
+                                ui.label(egui::RichText::new(&self.pf_ref(target).address_bl[i].0).monospace());
                                 
                                 ui.with_layout(Layout::right_to_left(), |ui| {
                                     if ui
                                         .button(RichText::new("-").monospace().color(Color32::RED))
                                         .clicked()
                                     {
-                                        self.pf.address_bl.remove(i);
+                                        self.pf_mut(target).address_bl.remove(i);
                                         removed = true;
                                     }
 
                                     if ui.button(RichText::new("Edit").monospace()).clicked() {
-                                        self.pf.address_bl[i].1 = true;
+                                        self.pf_mut(target).address_bl[i].1 = true;
                                     }
                                 });
                             });
@@ -1304,10 +2171,10 @@ impl VORGUI {
                                 ui.with_layout(Layout::right_to_left(), |ui| {
                                     if ui.button("Save").clicked() {
                                         // Save to file
-                                        self.pf.address_bl[i].1 = false;
-                                        self.save_pf_config();
+                                        self.pf_mut(target).address_bl[i].1 = false;
+                                        self.save_pf_config(target);
                                     }
-                                    ui.text_edit_singleline(&mut self.pf.address_bl[i].0);
+                                    ui.text_edit_singleline(&mut self.pf_mut(target).address_bl[i].0);
                                 });
                             });
                         });
@@ -1317,7 +2184,7 @@ impl VORGUI {
         }
     }
 
-    fn add_pf_bl(&mut self, ui: &mut egui::Ui) {
+    fn add_pf_bl(&mut self, ui: &mut egui::Ui, target: PFTarget) {
         // if not adding
         if !self.pf_bl_new.1 {
             ui.horizontal(|ui| {
@@ -1349,7 +2216,8 @@ impl VORGUI {
                     }
                     if ui.button("Add filter").clicked() {
                         self.pf_bl_new.1 = false;
-                        self.pf.address_bl.push(self.pf_bl_new.clone());
+                        let new_filter = self.pf_bl_new.clone();
+                        self.pf_mut(target).address_bl.push(new_filter);
                         self.pf_bl_new.0.clear();
                     }
                 });
@@ -1358,6 +2226,52 @@ impl VORGUI {
     }
 } // impl VORGUI
 
+// Editor for one list of (match, replacement) rewrite rules
+// Multicast options only apply to UDP routes sending to a multicast group
+fn is_multicast_host(app: &VORConfig) -> bool {
+    app.transport == Transport::Udp
+        && app.app_host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_multicast())
+}
+
+// IPv4 app hosts that could be a subnet's broadcast address
+fn is_unicast_v4_host(app: &VORConfig) -> bool {
+    app.transport == Transport::Udp
+        && app.app_host.parse::<std::net::Ipv4Addr>().is_ok_and(|ip| !ip.is_multicast() && !ip.is_broadcast())
+}
+
+fn rewrite_rule_list(
+    ui: &mut egui::Ui,
+    title: &str,
+    match_label: &str,
+    rules: &mut RewriteRules,
+    list: fn(&mut RewriteRules) -> &mut Vec<(String, String)>,
+) {
+    let list = list(rules);
+    ui.horizontal(|ui| {
+        ui.label(title);
+        ui.with_layout(Layout::right_to_left(), |ui| {
+            if ui.button(RichText::new("+").color(Color32::GREEN)).clicked() {
+                list.push((String::new(), String::new()));
+            }
+        });
+    });
+    let mut remove = None;
+    for (i, (from, to)) in list.iter_mut().enumerate() {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(format!("{}: ", match_label));
+            ui.add(egui::TextEdit::singleline(from).desired_width(150.));
+            ui.label("->");
+            ui.add(egui::TextEdit::singleline(to).desired_width(150.));
+            if ui.button(RichText::new("-").monospace().color(Color32::RED)).clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        list.remove(i);
+    }
+}
+
 impl App for VORGUI {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.set_tab(&ctx);
@@ -1384,36 +2298,43 @@ impl App for VORGUI {
                     ScrollArea::new([false, true]).show(ui, |ui| {
                         self.list_app_configs(ui);
                         self.add_app(ui);
+                        self.discover_apps(ui);
                         ui.add_space(60.);
                     });
                 }
                 VORGUITab::Firewall => {
+                    let target = self.pf_target;
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.pf_target, PFTarget::Incoming, "VRChat -> Apps");
+                        ui.selectable_value(&mut self.pf_target, PFTarget::Return, "Apps -> VRChat");
+                    });
+                    ui.separator();
                     ui.horizontal(|ui| {
-                        ui.checkbox(&mut self.pf.enabled, "OSC Packet Filter");
+                        ui.checkbox(&mut self.pf_mut(target).enabled, "OSC Packet Filter");
                         ui.with_layout(Layout::right_to_left(), |ui| {
                             if ui.button("Save").clicked() {
-                                self.save_pf_config();
+                                self.save_pf_config(target);
                             }
                         });
                     });
 
                     ui.separator();
-                    self.pf_buttons(ui);
-                    if self.pf.enabled {
+                    self.pf_buttons(ui, target);
+                    if self.pf_ref(target).enabled {
                         ui.separator();
 
-                        if self.pf.wl_enabled {
+                        if self.pf_ref(target).wl_enabled {
                             ui.label(RichText::new("Whitelist"));
                             ScrollArea::new([false, true]).show(ui, |ui| {
-                                self.pf_whitelist(ui);
-                                self.add_pf_wl(ui);
+                                self.pf_whitelist(ui, target);
+                                self.add_pf_wl(ui, target);
                                 ui.add_space(60.);
                             });
-                        } else if self.pf.bl_enabled {
+                        } else if self.pf_ref(target).bl_enabled {
                             ui.label(RichText::new("Blacklist"));
                             ScrollArea::new([false, true]).show(ui, |ui| {
-                                self.pf_blacklist(ui);
-                                self.add_pf_bl(ui);
+                                self.pf_blacklist(ui, target);
+                                self.add_pf_bl(ui, target);
                                 ui.add_space(60.);
                             });
                         }
@@ -1,5 +1,5 @@
 use directories::BaseDirs;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::path::Path;
 
 pub fn check_valid_port(port: &String) -> bool {
@@ -14,12 +14,130 @@ pub fn check_valid_port(port: &String) -> bool {
     }
 }
 
-pub fn check_valid_ipv4(ip: &String) -> bool {
-    if ip.parse::<Ipv4Addr>().is_err() {
-        false
+// IPv4/IPv6 literal or DNS name
+pub fn check_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+
+    if host.is_empty() || host.len() > 253 {
+        return false;
+    }
+    host.trim_end_matches('.').split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+// host:port target string. IPv6 literals are bracketed ([::1]:9000)
+pub fn host_port(host: &str, port: &str) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
     } else {
-        true
+        format!("{}:{}", host, port)
+    }
+}
+
+/*
+    Resolve a host:port target to a single socket address.
+    Names are looked up once here, callers keep the address for the life of the route.
+*/
+pub fn resolve_target(target: &str) -> Result<SocketAddr, String> {
+    match target.to_socket_addrs() {
+        Ok(mut addrs) => match addrs.next() {
+            Some(addr) => Ok(addr),
+            None => Err(format!("No addresses found for {}", target)),
+        },
+        Err(e) => Err(format!("Failed to resolve {}: {}", target, e)),
+    }
+}
+
+// Wildcard address on an OS assigned port in the same family as the target
+pub fn unspecified_for(target: &SocketAddr) -> SocketAddr {
+    match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+// Only addresses of this machine's interfaces can be bound
+pub fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+/*
+    Bind a UDP socket.
+    Binding the IPv6 wildcard (::) accepts IPv4 senders too on every OS (Windows defaults to IPv6 only).
+*/
+pub fn bind_udp(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    let sock = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    if let SocketAddr::V6(v6) = addr {
+        if v6.ip().is_unspecified() {
+            sock.set_only_v6(false)?;
+        }
+    }
+    sock.bind(&addr.into())?;
+    Ok(sock.into())
+}
+
+// Join the listener to a multicast group on the default interface. Empty group does nothing.
+pub fn join_multicast(sock: &UdpSocket, group: &str) -> std::io::Result<()> {
+    if group.is_empty() {
+        return Ok(());
+    }
+
+    match group.parse::<IpAddr>() {
+        Ok(IpAddr::V4(g)) if g.is_multicast() => sock.join_multicast_v4(&g, &Ipv4Addr::UNSPECIFIED),
+        Ok(IpAddr::V6(g)) if g.is_multicast() => sock.join_multicast_v6(&g, 0),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a multicast group", group),
+        )),
+    }
+}
+
+/*
+    Socket options for sending to multicast groups and broadcast addresses.
+    Empty TTL keeps the OS default (1).
+    Broadcast is only allowed for 255.255.255.255 or when the host is marked as a (subnet) broadcast address.
+*/
+pub fn configure_udp_target(
+    sock: &UdpSocket,
+    target: &SocketAddr,
+    multicast_ttl: &str,
+    multicast_loop: bool,
+    broadcast: bool,
+) -> std::io::Result<()> {
+    match target.ip() {
+        IpAddr::V4(ip) if ip.is_multicast() => {
+            if let Ok(ttl) = multicast_ttl.parse::<u32>() {
+                sock.set_multicast_ttl_v4(ttl)?;
+            }
+            sock.set_multicast_loop_v4(multicast_loop)?;
+        }
+        IpAddr::V4(ip) if ip.is_broadcast() || broadcast => sock.set_broadcast(true)?,
+        IpAddr::V4(_) => {}
+        IpAddr::V6(ip) if ip.is_multicast() => {
+            if let Ok(hops) = multicast_ttl.parse::<u32>() {
+                socket2::SockRef::from(sock).set_multicast_hops_v6(hops)?;
+            }
+            sock.set_multicast_loop_v6(multicast_loop)?;
+        }
+        IpAddr::V6(_) => {}
     }
+    Ok(())
+}
+
+// Empty or 0-255
+pub fn check_valid_ttl(ttl: &str) -> bool {
+    ttl.is_empty() || ttl.parse::<u8>().is_ok()
 }
 
 pub fn path_exists(p: &String) -> bool {
@@ -39,3 +157,8 @@ pub fn get_user_home_dir() -> String {
         .expect("[-] Failed to get user's home directory.");
     bd.to_string()
 }
+
+// Empty counts as valid for optional numeric settings
+pub fn check_valid_u64(n: &str) -> bool {
+    n.is_empty() || n.parse::<u64>().is_ok()
+}
@@ -0,0 +1,307 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rosc::{OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tungstenite::handshake::server::{NoCallback, ServerHandshake};
+use tungstenite::handshake::{HandshakeError, MidHandshake};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use crate::config::{VORAppIdentifier, VORAppStatus};
+use crate::packet::{OscBuf, SharedOscBuf};
+use crate::transport::SinkStatus;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Clients that fall behind are dropped once this many bytes are waiting to be sent to them
+const MAX_WRITE_BUFFER: usize = 1024 * 1024;
+
+// A client's handshake waiting on the client and when it connected
+type PendingHandshake = (MidHandshake<ServerHandshake<TcpStream, NoCallback>>, Instant);
+
+// How packets are pushed to WebSocket clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum WsFormat {
+    // Raw OSC packet in a binary message
+    #[default]
+    Binary,
+    // Decoded OSC packet as JSON in a text message
+    Json,
+}
+
+/*
+    Local WebSocket server for a route.
+    The app host and port are the server's bind address.
+    Every packet sent to the route is pushed to all connected clients.
+    The server thread owns the route's status and reports the client count.
+*/
+pub struct WsSink {
+    packet_tx: Sender<SharedOscBuf>,
+    stop: Arc<AtomicBool>,
+    status: SinkStatus,
+    // Taken on drop so the port is free for the next route right away
+    listener: Arc<Mutex<Option<TcpListener>>>,
+}
+
+impl WsSink {
+    pub fn new(
+        ai: i64,
+        bind_addr: SocketAddr,
+        format: WsFormat,
+        app_stat_tx: Sender<VORAppIdentifier>,
+    ) -> Self {
+        let (packet_tx, packet_rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let status = SinkStatus::new(ai, app_stat_tx);
+        let listener = Arc::new(Mutex::new(None));
+
+        let (thread_stop, thread_status, thread_listener) = (stop.clone(), status.clone(), listener.clone());
+        thread::spawn(move || ws_server(bind_addr, format, thread_status, thread_listener, packet_rx, thread_stop));
+        Self {
+            packet_tx,
+            stop,
+            status,
+            listener,
+        }
+    }
+
+    pub fn send(&self, buf: &SharedOscBuf) {
+        let _ = self.packet_tx.send(buf.clone());
+    }
+}
+
+impl Drop for WsSink {
+    // Dropped from async route tasks, so the server thread is left to end on its own instead of joined
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.status.close();
+        *self.listener.lock().unwrap() = None;
+    }
+}
+
+fn ws_server(
+    bind_addr: SocketAddr,
+    format: WsFormat,
+    status: SinkStatus,
+    listener: Arc<Mutex<Option<TcpListener>>>,
+    packet_rx: mpsc::Receiver<SharedOscBuf>,
+    stop: Arc<AtomicBool>,
+) {
+    {
+        // Not bound if the sink was already dropped
+        let mut slot = listener.lock().unwrap();
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        match TcpListener::bind(bind_addr).and_then(|l| {
+            l.set_nonblocking(true)?;
+            Ok(l)
+        }) {
+            Ok(l) => *slot = Some(l),
+            Err(_e) => {
+                status.error(-2, format!("Failed to bind WebSocket server: {}", _e));
+                return;
+            }
+        }
+    }
+
+    let send_clients = |n: usize| status.send(VORAppStatus::Clients(n));
+
+    let mut clients: Vec<WebSocket<TcpStream>> = Vec::new();
+    let mut handshakes: Vec<PendingHandshake> = Vec::new();
+    send_clients(0);
+
+    while !stop.load(Ordering::Relaxed) {
+        let client_count = clients.len();
+
+        // New clients. Handshakes are non-blocking so a slow client can't stall the route.
+        while let Some(stream) = ws_accept(&listener) {
+            if stream.set_nonblocking(true).is_ok() {
+                let config = WebSocketConfig {
+                    max_write_buffer_size: MAX_WRITE_BUFFER,
+                    ..Default::default()
+                };
+                let res = tungstenite::accept_with_config(stream, Some(config));
+                ws_handshake(res, Instant::now(), &mut clients, &mut handshakes);
+            }
+        }
+        for (mid, connected) in std::mem::take(&mut handshakes) {
+            if connected.elapsed() < HANDSHAKE_TIMEOUT {
+                ws_handshake(mid.handshake(), connected, &mut clients, &mut handshakes);
+            }
+        }
+
+        match packet_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(buf) => {
+                if let Some(msg) = ws_message(format, &buf) {
+                    clients.retain_mut(|ws| ws_send(ws, msg.clone()));
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Answer pings and drop closed clients
+        clients.retain_mut(ws_poll);
+
+        if clients.len() != client_count {
+            send_clients(clients.len());
+        }
+    }
+
+    for mut ws in clients {
+        let _ = ws.close(None);
+        let _ = ws.flush();
+    }
+}
+
+// Next pending connection. None once the sink dropped the listener.
+fn ws_accept(listener: &Mutex<Option<TcpListener>>) -> Option<TcpStream> {
+    let listener = listener.lock().unwrap();
+    listener.as_ref()?.accept().ok().map(|(stream, _addr)| stream)
+}
+
+// Finished handshakes become clients, the ones waiting on the client are retried next poll
+fn ws_handshake(
+    res: Result<WebSocket<TcpStream>, HandshakeError<ServerHandshake<TcpStream, NoCallback>>>,
+    connected: Instant,
+    clients: &mut Vec<WebSocket<TcpStream>>,
+    handshakes: &mut Vec<PendingHandshake>,
+) {
+    match res {
+        Ok(ws) => clients.push(ws),
+        Err(HandshakeError::Interrupted(mid)) => handshakes.push((mid, connected)),
+        Err(HandshakeError::Failure(_e)) => {}
+    }
+}
+
+// Returns false if the client is gone or fell behind
+fn ws_send(ws: &mut WebSocket<TcpStream>, msg: Message) -> bool {
+    match ws.send(msg) {
+        Ok(()) => true,
+        // Queued in the WebSocket's write buffer
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => true,
+        // Also WriteBufferFull, the client isn't reading fast enough
+        Err(_e) => false,
+    }
+}
+
+// Returns false if the client is gone
+fn ws_poll(ws: &mut WebSocket<TcpStream>) -> bool {
+    loop {
+        match ws.read() {
+            // Client messages are ignored
+            Ok(_msg) => continue,
+            Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => return true,
+            Err(_e) => return false,
+        }
+    }
+}
+
+fn ws_message(format: WsFormat, buf: &OscBuf) -> Option<Message> {
+    match format {
+        WsFormat::Binary => Some(Message::Binary(buf.bytes.clone())),
+        // Bad packets can't be shown as JSON
+        WsFormat::Json => buf
+            .pkt
+            .as_ref()
+            .map(|pkt| Message::Text(packet_json(pkt).to_string())),
+    }
+}
+
+/*
+    JSON form of an OSC packet:
+        Message: {"address": "/avatar/parameters/Foo", "args": [1.0]}
+        Bundle: {"timetag": [seconds, fractional], "packets": [...]}
+*/
+fn packet_json(pkt: &OscPacket) -> Value {
+    match pkt {
+        OscPacket::Message(msg) => json!({
+            "address": msg.addr,
+            "args": msg.args.iter().map(arg_json).collect::<Vec<Value>>(),
+        }),
+        OscPacket::Bundle(bundle) => json!({
+            "timetag": [bundle.timetag.seconds, bundle.timetag.fractional],
+            "packets": bundle.content.iter().map(packet_json).collect::<Vec<Value>>(),
+        }),
+    }
+}
+
+fn arg_json(arg: &OscType) -> Value {
+    match arg {
+        OscType::Int(i) => json!(i),
+        OscType::Float(f) => json!(f),
+        OscType::String(s) => json!(s),
+        OscType::Blob(b) => json!(b),
+        OscType::Time(t) => json!([t.seconds, t.fractional]),
+        OscType::Long(l) => json!(l),
+        OscType::Double(d) => json!(d),
+        OscType::Char(c) => json!(c.to_string()),
+        OscType::Color(c) => json!({"r": c.red, "g": c.green, "b": c.blue, "a": c.alpha}),
+        OscType::Midi(m) => json!({"port": m.port, "status": m.status, "data1": m.data1, "data2": m.data2}),
+        OscType::Bool(b) => json!(b),
+        OscType::Array(a) => json!(a.content.iter().map(arg_json).collect::<Vec<Value>>()),
+        OscType::Nil => Value::Null,
+        OscType::Inf => json!("Infinity"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tungstenite::stream::MaybeTlsStream;
+
+    // Wait for the server to report its client count
+    fn client_count(app_stat_rx: &mpsc::Receiver<VORAppIdentifier>) -> usize {
+        loop {
+            let status = app_stat_rx.recv_timeout(HANDSHAKE_TIMEOUT).unwrap();
+            if let VORAppStatus::Clients(n) = status.status {
+                return n;
+            }
+        }
+    }
+
+    #[test]
+    fn silent_client_does_not_stall_handshakes() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (app_stat_tx, app_stat_rx) = mpsc::channel();
+        let sink = WsSink::new(0, addr, WsFormat::Binary, app_stat_tx);
+        assert_eq!(client_count(&app_stat_rx), 0);
+
+        // Connects but never sends its handshake
+        let mut silent = TcpStream::connect(addr).unwrap();
+        silent.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        thread::sleep(POLL_INTERVAL * 2);
+
+        let connecting = Instant::now();
+        let (mut client, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+        assert!(connecting.elapsed() < HANDSHAKE_TIMEOUT);
+        assert_eq!(client_count(&app_stat_rx), 1);
+
+        if let MaybeTlsStream::Plain(s) = client.get_ref() {
+            s.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).unwrap();
+        }
+        let buf = Arc::new(OscBuf::decode(b"/a\0\0,\0\0\0"));
+        sink.send(&buf);
+        assert_eq!(client.read().unwrap(), Message::Binary(buf.bytes.clone()));
+    }
+
+    #[test]
+    fn dropped_sink_frees_port() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let (app_stat_tx, app_stat_rx) = mpsc::channel();
+        let sink = WsSink::new(0, addr, WsFormat::Binary, app_stat_tx);
+        assert_eq!(client_count(&app_stat_rx), 0);
+
+        drop(sink);
+        assert!(TcpListener::bind(addr).is_ok());
+    }
+}